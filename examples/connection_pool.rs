@@ -0,0 +1,69 @@
+use dataloader::batch_fn;
+use dataloader::cached::Loader;
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// Stands in for a cheaply cloneable handle to a connection pool, such as `sqlx::PgPool`, or an
+// HTTP client such as `reqwest::Client`.
+#[derive(Clone, Default)]
+struct Pool {
+    queries: Arc<AtomicUsize>,
+}
+
+#[derive(Debug, Clone)]
+struct User {
+    id: i64,
+    name: String,
+}
+
+impl Pool {
+    // With sqlx this would be
+    // `sqlx::query_as!(User, "SELECT id, name FROM users WHERE id = ANY($1)", &ids[..])
+    //     .fetch_all(self).await`.
+    async fn fetch_users(&self, ids: &[i64]) -> Vec<User> {
+        self.queries.fetch_add(1, Ordering::SeqCst);
+        ids.iter()
+            .filter(|id| **id > 0)
+            .map(|id| User {
+                id: *id,
+                name: format!("user {}", id),
+            })
+            .collect()
+    }
+}
+
+fn main() {
+    let pool = Pool::default();
+
+    // The closure owns a pool handle and clones it into each batch's future, which then owns
+    // everything it uses.
+    let loader = Loader::new(batch_fn({
+        let pool = pool.clone();
+        move |ids: Vec<i64>| {
+            let pool = pool.clone();
+            async move {
+                let users = pool.fetch_users(&ids).await;
+                users
+                    .into_iter()
+                    .map(|user| (user.id, user))
+                    .collect::<HashMap<_, _>>()
+            }
+        }
+    }))
+    .with_concurrent_dispatch();
+
+    let (users, missing) = block_on(futures::future::join(
+        loader.load_many(vec![1, 2, 3]),
+        loader.try_load(-1),
+    ));
+    for id in 1..=3 {
+        println!("{:?}", users[&id]);
+    }
+    println!("{:?}", missing);
+
+    let user = block_on(loader.load(2));
+    println!("cached: {}", user.name);
+    println!("queries: {}", pool.queries.load(Ordering::SeqCst));
+}
@@ -0,0 +1,60 @@
+use dataloader::cached::Loader;
+use dataloader::indexed_batch_fn;
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::future::ready;
+
+#[derive(Debug, Clone)]
+struct User {
+    id: i32,
+    name: String,
+}
+
+#[derive(Debug, Clone)]
+struct DbError(String);
+
+// Stands in for `SELECT id, name FROM users WHERE id = ANY($1)`, with the rows ordered to match
+// the requested ids and a `None` for every id that has no row.
+async fn select_users(
+    table: &HashMap<i32, User>,
+    ids: &[i32],
+) -> Result<Vec<Option<User>>, DbError> {
+    if ids.iter().any(|id| *id < 0) {
+        return Err(DbError("invalid id".to_string()));
+    }
+    ready(Ok(ids.iter().map(|id| table.get(id).cloned()).collect())).await
+}
+
+fn main() {
+    let table = (1..=3)
+        .map(|id| {
+            let user = User {
+                id,
+                name: format!("user {}", id),
+            };
+            (id, user)
+        })
+        .collect::<HashMap<_, _>>();
+
+    let loader = Loader::new(indexed_batch_fn(move |ids: Vec<i32>| {
+        let table = table.clone();
+        async move {
+            println!("query users {:?}", ids);
+            select_users(&table, &ids).await
+        }
+    }));
+
+    let (user, missing) = block_on(futures::future::join(loader.load(1), loader.try_load(4)));
+    if let Ok(user) = user {
+        println!("found user {}: {}", user.id, user.name);
+    }
+    println!("{:?}", missing);
+
+    let users = block_on(loader.load_many(vec![2, 3, -1]));
+    for (id, user) in users {
+        match user {
+            Ok(user) => println!("found user {}: {}", id, user.name),
+            Err(DbError(msg)) => println!("query for {} failed: {}", id, msg),
+        }
+    }
+}
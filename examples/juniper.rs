@@ -17,8 +17,8 @@ impl BatchFn<i32, Cult> for CultBatcher {
             .iter()
             .map(|k| {
                 let mut cult: Cult = Faker.fake();
-                cult.id = k.clone();
-                (k.clone(), cult)
+                cult.id = *k;
+                (*k, cult)
             })
             .collect();
         ready(ret).await
@@ -30,6 +30,12 @@ pub struct AppContext {
     cult_loader: Loader<i32, Cult, CultBatcher>,
 }
 
+impl Default for AppContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl AppContext {
     pub fn new() -> AppContext {
         AppContext {
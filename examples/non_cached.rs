@@ -10,10 +10,7 @@ struct MyLoadFn;
 impl BatchFn<usize, usize> for MyLoadFn {
     async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
         println!("BatchFn load keys {:?}", keys);
-        let ret = keys
-            .iter()
-            .map(|v| (v.clone(), v.clone()))
-            .collect::<HashMap<_, _>>();
+        let ret = keys.iter().map(|v| (*v, *v)).collect::<HashMap<_, _>>();
         ready(ret).await
     }
 }
@@ -0,0 +1,63 @@
+use dataloader::cached::Loader;
+use dataloader::BatchFn;
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::future::ready;
+use std::sync::Arc;
+
+// What the cache holds for a blob: its metadata and where to fetch the body from. Cloning the
+// `Arc` on every cache hit is a reference count bump, however large the blob is.
+#[derive(Debug)]
+struct BlobHandle {
+    id: u32,
+    size: usize,
+    location: String,
+}
+
+impl BlobHandle {
+    // Fetches the body on demand in chunks, standing in for a ranged read from object storage.
+    fn chunks(&self, chunk_size: usize) -> impl Iterator<Item = Vec<u8>> + '_ {
+        (0..self.size).step_by(chunk_size).map(move |offset| {
+            let len = chunk_size.min(self.size - offset);
+            vec![(self.id % 256) as u8; len]
+        })
+    }
+}
+
+struct BlobMetadataLoader;
+
+impl BatchFn<u32, Arc<BlobHandle>> for BlobMetadataLoader {
+    async fn load(&mut self, keys: &[u32]) -> HashMap<u32, Arc<BlobHandle>> {
+        println!("fetch metadata for blobs {:?}", keys);
+        let ret = keys
+            .iter()
+            .map(|id| {
+                let handle = BlobHandle {
+                    id: *id,
+                    size: *id as usize * 1024 * 1024,
+                    location: format!("s3://blobs/{}", id),
+                };
+                (*id, Arc::new(handle))
+            })
+            .collect();
+        ready(ret).await
+    }
+}
+
+fn main() {
+    let loader = Loader::new(BlobMetadataLoader);
+
+    let blobs = block_on(loader.load_many(vec![1, 2, 3]));
+    for id in 1..=3 {
+        let blob = &blobs[&id];
+        let streamed = blob
+            .chunks(256 * 1024)
+            .map(|chunk| chunk.len())
+            .sum::<usize>();
+        println!("streamed {} bytes of blob at {}", streamed, blob.location);
+    }
+
+    // served from the cache: the same handle, no copy of the payload
+    let again = block_on(loader.load(2));
+    println!("same handle: {}", Arc::ptr_eq(&again, &blobs[&2]));
+}
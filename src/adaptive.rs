@@ -0,0 +1,109 @@
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Early-flush threshold tuned from observed batch latency. The threshold grows while full
+/// batches come back no slower than the running average, and halves when a batch takes
+/// noticeably longer than it, down to `min`.
+pub(crate) struct AdaptiveBatchSize {
+    min: usize,
+    max: usize,
+    current: AtomicUsize,
+    // exponential moving average of batch latency, 0 until the first batch is observed
+    avg_latency_nanos: AtomicU64,
+}
+
+impl AdaptiveBatchSize {
+    pub(crate) fn new(min: usize, max: usize) -> Self {
+        let min = min.max(1);
+        AdaptiveBatchSize {
+            min,
+            max: max.max(min),
+            current: AtomicUsize::new(min),
+            avg_latency_nanos: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn max(&self) -> usize {
+        self.max
+    }
+
+    pub(crate) fn current(&self) -> usize {
+        self.current.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn observe(&self, batch_len: usize, elapsed: Duration) {
+        let elapsed = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        let avg = self.avg_latency_nanos.load(Ordering::Relaxed);
+        let current = self.current();
+
+        if avg != 0 && elapsed > avg.saturating_add(avg / 2) {
+            self.current
+                .store((current / 2).max(self.min), Ordering::Relaxed);
+        } else if batch_len >= current {
+            let grown = current.saturating_add((current / 4).max(1));
+            self.current.store(grown.min(self.max), Ordering::Relaxed);
+        }
+
+        let avg = if avg == 0 {
+            elapsed
+        } else {
+            avg - avg / 8 + elapsed / 8
+        };
+        self.avg_latency_nanos.store(avg, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AdaptiveBatchSize;
+    use std::time::Duration;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn grows_on_full_batches_up_to_max() {
+        let adaptive = AdaptiveBatchSize::new(4, 8);
+        adaptive.observe(4, MS);
+        assert_eq!(adaptive.current(), 5);
+        adaptive.observe(5, MS);
+        assert_eq!(adaptive.current(), 6);
+        // a batch that didn't fill up says nothing about a larger size
+        adaptive.observe(3, MS);
+        assert_eq!(adaptive.current(), 6);
+        for _ in 0..10 {
+            adaptive.observe(adaptive.current(), MS);
+        }
+        assert_eq!(adaptive.current(), 8);
+    }
+
+    #[test]
+    fn halves_on_slow_batches_down_to_min() {
+        let adaptive = AdaptiveBatchSize::new(2, 64);
+        for _ in 0..20 {
+            adaptive.observe(adaptive.current(), MS);
+        }
+        let grown = adaptive.current();
+        assert!(grown > 8);
+        adaptive.observe(grown, 2 * MS);
+        assert_eq!(adaptive.current(), grown / 2);
+        // up to one and a half times the average is not slow
+        let adaptive = AdaptiveBatchSize::new(4, 64);
+        adaptive.observe(4, 2 * MS);
+        adaptive.observe(5, 3 * MS);
+        assert_eq!(adaptive.current(), 6);
+        for _ in 0..10 {
+            adaptive.observe(1, Duration::from_secs(1));
+        }
+        assert_eq!(adaptive.current(), 4);
+    }
+
+    #[test]
+    fn clamps_bounds() {
+        let adaptive = AdaptiveBatchSize::new(0, 0);
+        assert_eq!((adaptive.current(), adaptive.max()), (1, 1));
+        adaptive.observe(1, Duration::MAX);
+        adaptive.observe(1, Duration::MAX);
+        assert_eq!(adaptive.current(), 1);
+    }
+}
@@ -0,0 +1,210 @@
+//! A cached loader for caches reached asynchronously, such as Redis or memcached.
+//!
+//! [`cached::Loader`] calls its [`crate::cached::Cache`] synchronously under its state lock, so
+//! a networked cache would hold up every load. [`Loader`] instead awaits an [`AsyncCache`]
+//! without any lock of its own and hands the misses to an inner loader that only batches and
+//! coalesces them.
+use crate::cached;
+use crate::runtime::Arc;
+use crate::{BatchFn, LoadError, WaitForWorkFn};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::future::Future;
+use std::hash::{BuildHasher, Hash};
+use std::sync::Mutex;
+
+/// A cache whose operations are awaited. Methods take `&self`, so concurrent loads can have
+/// several operations in flight at once and the backend handles its own concurrency, e.g.
+/// through a connection pool.
+pub trait AsyncCache {
+    type Key;
+    type Val;
+    fn get(&self, key: &Self::Key) -> impl Future<Output = Option<Self::Val>>;
+    fn insert(&self, key: Self::Key, val: Self::Val) -> impl Future<Output = ()>;
+    fn remove(&self, key: &Self::Key) -> impl Future<Output = Option<Self::Val>>;
+    fn clear(&self) -> impl Future<Output = ()>;
+
+    /// Looks up `keys` at once, with one result per key in the same order, e.g. through `MGET`.
+    /// The default calls [`AsyncCache::get`] for each key in turn.
+    fn get_many(&self, keys: &[Self::Key]) -> impl Future<Output = Vec<Option<Self::Val>>> {
+        async move {
+            let mut ret = Vec::with_capacity(keys.len());
+            for key in keys {
+                ret.push(self.get(key).await);
+            }
+            ret
+        }
+    }
+
+    /// Caches `entries` at once. The default calls [`AsyncCache::insert`] for each in turn.
+    fn insert_many(&self, entries: Vec<(Self::Key, Self::Val)>) -> impl Future<Output = ()> {
+        async move {
+            for (key, val) in entries {
+                self.insert(key, val).await;
+            }
+        }
+    }
+}
+
+/// An in-memory cache, locked only for the duration of each operation.
+impl<K, V, S: BuildHasher> AsyncCache for Mutex<HashMap<K, V, S>>
+where
+    K: Eq + Hash,
+    V: Clone,
+{
+    type Key = K;
+    type Val = V;
+
+    async fn get(&self, key: &K) -> Option<V> {
+        self.lock().unwrap().get(key).cloned()
+    }
+
+    async fn insert(&self, key: K, val: V) {
+        self.lock().unwrap().insert(key, val);
+    }
+
+    async fn remove(&self, key: &K) -> Option<V> {
+        self.lock().unwrap().remove(key)
+    }
+
+    async fn clear(&self) {
+        self.lock().unwrap().clear()
+    }
+
+    async fn get_many(&self, keys: &[K]) -> Vec<Option<V>> {
+        let map = self.lock().unwrap();
+        keys.iter().map(|key| map.get(key).cloned()).collect()
+    }
+
+    async fn insert_many(&self, entries: Vec<(K, V)>) {
+        self.lock().unwrap().extend(entries);
+    }
+}
+
+pub struct Loader<K, V, F, C = Mutex<HashMap<K, V>>>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: BatchFn<K, V>,
+    C: AsyncCache<Key = K, Val = V>,
+{
+    inner: cached::Loader<K, V, F>,
+    cache: Arc<C>,
+}
+
+impl<K, V, F, C> Clone for Loader<K, V, F, C>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: BatchFn<K, V>,
+    C: AsyncCache<Key = K, Val = V>,
+{
+    fn clone(&self) -> Self {
+        Loader {
+            inner: self.inner.clone(),
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+#[allow(clippy::implicit_hasher)]
+impl<K, V, F> Loader<K, V, F, Mutex<HashMap<K, V>>>
+where
+    K: Eq + Hash + Clone + Debug,
+    V: Clone,
+    F: BatchFn<K, V>,
+{
+    pub fn new(load_fn: F) -> Self {
+        Loader::with_async_cache(load_fn, Mutex::new(HashMap::new()))
+    }
+}
+
+impl<K, V, F, C> Loader<K, V, F, C>
+where
+    K: Eq + Hash + Clone + Debug,
+    V: Clone,
+    F: BatchFn<K, V>,
+    C: AsyncCache<Key = K, Val = V>,
+{
+    /// Caches the values `load_fn` returns in `cache`, which is awaited without holding any
+    /// lock, so a slow operation only holds up the load waiting on it.
+    pub fn with_async_cache(load_fn: F, cache: C) -> Self {
+        Loader {
+            inner: cached::Loader::new(load_fn).with_cache_if(|_| false),
+            cache: Arc::new(cache),
+        }
+    }
+
+    pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.inner = self.inner.with_max_batch_size(max_batch_size);
+        self
+    }
+
+    pub fn with_yield_count(mut self, yield_count: usize) -> Self {
+        self.inner = self.inner.with_yield_count(yield_count);
+        self
+    }
+
+    pub fn with_custom_wait_for_work(mut self, wait_for_work_fn: impl WaitForWorkFn) -> Self {
+        self.inner = self.inner.with_custom_wait_for_work(wait_for_work_fn);
+        self
+    }
+
+    /// Serves `key` from the cache, or loads it and caches the value. Concurrent misses of the
+    /// same key share one batch, and each caches the value it gets.
+    pub async fn try_load(&self, key: K) -> Result<V, LoadError<K>> {
+        if let Some(v) = self.cache.get(&key).await {
+            return Ok(v);
+        }
+        let v = self.inner.try_load(key.clone()).await?;
+        self.cache.insert(key, v.clone()).await;
+        Ok(v)
+    }
+
+    pub async fn load(&self, key: K) -> V {
+        self.try_load(key).await.unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Looks up `keys` with one [`AsyncCache::get_many`], loads the misses in as few batches as
+    /// possible and caches them with one [`AsyncCache::insert_many`].
+    pub async fn try_load_many(&self, keys: Vec<K>) -> Result<HashMap<K, V>, LoadError<K>> {
+        let mut ret = HashMap::new();
+        let mut misses = Vec::new();
+        let mut hits = self.cache.get_many(&keys).await.into_iter();
+        for key in keys.into_iter() {
+            match hits.next().flatten() {
+                Some(v) => {
+                    ret.insert(key, v);
+                }
+                None => misses.push(key),
+            }
+        }
+        if !misses.is_empty() {
+            let loaded = self.inner.try_load_many(misses).await?;
+            self.cache
+                .insert_many(loaded.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+                .await;
+            ret.extend(loaded);
+        }
+        Ok(ret)
+    }
+
+    pub async fn load_many(&self, keys: Vec<K>) -> HashMap<K, V> {
+        self.try_load_many(keys)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub async fn prime(&self, key: K, val: V) {
+        self.cache.insert(key, val).await
+    }
+
+    /// Removes `key` from the cache, returning the value it held, if any.
+    pub async fn clear(&self, key: K) -> Option<V> {
+        self.cache.remove(&key).await
+    }
+
+    pub async fn clear_all(&self) {
+        self.cache.clear().await
+    }
+}
@@ -1,5 +1,172 @@
 use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::io::{Error, ErrorKind};
 
 pub trait BatchFn<K, V> {
     fn load(&mut self, keys: &[K]) -> impl std::future::Future<Output = HashMap<K, V>>;
+
+    /// Writes `values` to the backend, backing [`crate::cached::Loader::store`].
+    /// The default rejects every write, for read-only batch functions.
+    fn store_many(&mut self, _values: &[(K, V)]) -> impl Future<Output = Result<(), Error>> {
+        async {
+            Err(Error::new(
+                ErrorKind::Unsupported,
+                "batch function does not support store_many",
+            ))
+        }
+    }
+}
+
+/// A batch function that serves batches through a shared reference, so several can be in flight
+/// at once. `Arc<F>` implements [`BatchFn`] for every such `F` and is cheap to clone, which makes
+/// it a natural fit for [`crate::cached::Loader::with_concurrent_dispatch`].
+pub trait SharedBatchFn<K, V> {
+    fn load(&self, keys: &[K]) -> impl Future<Output = HashMap<K, V>>;
+}
+
+impl<K, V, F> BatchFn<K, V> for std::sync::Arc<F>
+where
+    F: SharedBatchFn<K, V>,
+{
+    fn load(&mut self, keys: &[K]) -> impl Future<Output = HashMap<K, V>> {
+        F::load(self, keys)
+    }
+}
+
+/// Builds a [`BatchFn`] from a closure. The closure gets an owned copy of each batch's keys,
+/// so the future it returns can be `async move` over state captured by the closure, such as a
+/// clone of a connection pool or HTTP client:
+///
+/// ```ignore
+/// let loader = Loader::new(batch_fn(move |ids: Vec<i64>| {
+///     let pool = pool.clone();
+///     async move { fetch_users(&pool, &ids).await }
+/// }));
+/// ```
+///
+/// The adapter is `Clone` when the closure is, which allows
+/// [`crate::cached::Loader::with_concurrent_dispatch`].
+pub fn batch_fn<K, V, G, Fut>(f: G) -> FnBatchFn<G>
+where
+    G: FnMut(Vec<K>) -> Fut,
+    Fut: Future<Output = HashMap<K, V>>,
+{
+    FnBatchFn { f }
+}
+
+/// The [`BatchFn`] returned by [`batch_fn`].
+#[derive(Clone)]
+pub struct FnBatchFn<G> {
+    f: G,
+}
+
+impl<K, V, G, Fut> BatchFn<K, V> for FnBatchFn<G>
+where
+    K: Clone,
+    G: FnMut(Vec<K>) -> Fut,
+    Fut: Future<Output = HashMap<K, V>>,
+{
+    fn load(&mut self, keys: &[K]) -> impl Future<Output = HashMap<K, V>> {
+        (self.f)(keys.to_vec())
+    }
+}
+
+/// Like [`batch_fn`], but the closure's future returns any collection of key-value pairs
+/// instead of a `HashMap`, e.g. a `Vec<(K, V)>` from a backend that returns rows in order.
+/// Keys missing from the pairs stay unresolved, and a repeated key keeps its last value.
+pub fn pairs_batch_fn<K, V, G, Fut>(f: G) -> PairsBatchFn<G>
+where
+    G: FnMut(Vec<K>) -> Fut,
+    Fut: Future,
+    Fut::Output: IntoIterator<Item = (K, V)>,
+{
+    PairsBatchFn { f }
+}
+
+/// The [`BatchFn`] returned by [`pairs_batch_fn`].
+#[derive(Clone)]
+pub struct PairsBatchFn<G> {
+    f: G,
+}
+
+impl<K, V, G, Fut> BatchFn<K, V> for PairsBatchFn<G>
+where
+    K: Eq + Hash + Clone,
+    G: FnMut(Vec<K>) -> Fut,
+    Fut: Future,
+    Fut::Output: IntoIterator<Item = (K, V)>,
+{
+    async fn load(&mut self, keys: &[K]) -> HashMap<K, V> {
+        (self.f)(keys.to_vec()).await.into_iter().collect()
+    }
+}
+
+/// Wraps a [`BatchFn`] so its values are cached and handed out as `Arc<V>`, which makes every
+/// cache hit a reference-count bump instead of a deep clone of a large value:
+///
+/// ```ignore
+/// let loader: Loader<u64, Arc<Report>, _> = Loader::new(arc_batch_fn(ReportBatchFn));
+/// ```
+pub fn arc_batch_fn<F>(load_fn: F) -> ArcBatchFn<F> {
+    ArcBatchFn { load_fn }
+}
+
+/// The [`BatchFn`] returned by [`arc_batch_fn`].
+#[derive(Clone)]
+pub struct ArcBatchFn<F> {
+    load_fn: F,
+}
+
+impl<K, V, F> BatchFn<K, std::sync::Arc<V>> for ArcBatchFn<F>
+where
+    K: Eq + Hash,
+    F: BatchFn<K, V>,
+{
+    async fn load(&mut self, keys: &[K]) -> HashMap<K, std::sync::Arc<V>> {
+        let ret = self.load_fn.load(keys).await;
+        ret.into_iter()
+            .map(|(k, v)| (k, std::sync::Arc::new(v)))
+            .collect()
+    }
+}
+
+/// Builds a [`BatchFn`] from a closure whose results are aligned positionally with the keys it
+/// was given, the shape most SQL-ish backends return (`Result<Vec<Option<Row>>, DbError>`).
+///
+/// Each key resolves to `Ok(value)` for a `Some` at its position. A `None`, or a missing trailing
+/// position, leaves the key unresolved. If the closure returns `Err`, every key in the batch
+/// resolves to a clone of that error.
+pub fn indexed_batch_fn<K, V, E, G, Fut>(f: G) -> IndexedBatchFn<G>
+where
+    G: FnMut(Vec<K>) -> Fut,
+    Fut: Future<Output = Result<Vec<Option<V>>, E>>,
+{
+    IndexedBatchFn { f }
+}
+
+/// The [`BatchFn`] returned by [`indexed_batch_fn`].
+#[derive(Clone)]
+pub struct IndexedBatchFn<G> {
+    f: G,
+}
+
+impl<K, V, E, G, Fut> BatchFn<K, Result<V, E>> for IndexedBatchFn<G>
+where
+    K: Eq + Hash + Clone,
+    E: Clone,
+    G: FnMut(Vec<K>) -> Fut,
+    Fut: Future<Output = Result<Vec<Option<V>>, E>>,
+{
+    async fn load(&mut self, keys: &[K]) -> HashMap<K, Result<V, E>> {
+        match (self.f)(keys.to_vec()).await {
+            Ok(rows) => keys
+                .iter()
+                .cloned()
+                .zip(rows)
+                .filter_map(|(k, row)| row.map(|v| (k, Ok(v))))
+                .collect(),
+            Err(e) => keys.iter().map(|k| (k.clone(), Err(e.clone()))).collect(),
+        }
+    }
 }
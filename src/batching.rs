@@ -0,0 +1,84 @@
+//! Stand-alone batching primitives, for pipelines that want the loader's coalescing behavior
+//! without its caching or request bookkeeping.
+
+use crate::runtime;
+use futures_core::Stream;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// Groups the items of `input` into batches of at most `max_size` items.
+///
+/// A batch is emitted as soon as it is full, or once `window` has elapsed since its first item
+/// arrived, whichever comes first. Any partial batch is emitted when `input` ends. A `max_size`
+/// of 0 is treated as 1.
+pub fn batch_stream<S>(input: S, max_size: usize, window: Duration) -> BatchStream<S>
+where
+    S: Stream,
+{
+    BatchStream {
+        input: Box::pin(input),
+        max_size: max_size.max(1),
+        window,
+        buffer: Vec::new(),
+        deadline: None,
+        done: false,
+    }
+}
+
+/// The stream returned by [`batch_stream`].
+pub struct BatchStream<S: Stream> {
+    input: Pin<Box<S>>,
+    max_size: usize,
+    window: Duration,
+    buffer: Vec<S::Item>,
+    deadline: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+    done: bool,
+}
+
+impl<S: Stream> BatchStream<S> {
+    fn take_batch(&mut self) -> Vec<S::Item> {
+        self.deadline = None;
+        mem::take(&mut self.buffer)
+    }
+}
+
+impl<S: Stream> Unpin for BatchStream<S> {}
+
+impl<S: Stream> Stream for BatchStream<S> {
+    type Item = Vec<S::Item>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        while !this.done {
+            match this.input.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    this.buffer.push(item);
+                    if this.buffer.len() >= this.max_size {
+                        return Poll::Ready(Some(this.take_batch()));
+                    }
+                    if this.deadline.is_none() {
+                        this.deadline = Some(Box::pin(runtime::sleep(this.window)));
+                    }
+                }
+                Poll::Ready(None) => this.done = true,
+                Poll::Pending => break,
+            }
+        }
+
+        if this.done {
+            return if this.buffer.is_empty() {
+                Poll::Ready(None)
+            } else {
+                Poll::Ready(Some(this.take_batch()))
+            };
+        }
+
+        match this.deadline.as_mut().map(|d| d.as_mut().poll(cx)) {
+            Some(Poll::Ready(())) => Poll::Ready(Some(this.take_batch())),
+            _ => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,579 @@
+//! [`Cache`] implementations for bounding what a [`crate::cached::Loader`] keeps.
+use crate::cached::{Cache, EvictFn};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// Holds up to `capacity` entries, evicting the least recently used one to make room. Both
+/// [`Cache::get`] and [`Cache::insert`] count as a use. Evicted entries are reported through
+/// [`Cache::set_on_evict`].
+pub struct LruCache<K, V> {
+    capacity: usize,
+    // entries with the tick they were last used at
+    entries: HashMap<K, (V, u64)>,
+    recency: BTreeMap<u64, K>,
+    tick: u64,
+    on_evict: Option<EvictFn<K, V>>,
+}
+
+impl<K, V> LruCache<K, V>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Creates an empty cache holding at most `capacity` entries, and at least one.
+    pub fn new(capacity: usize) -> Self {
+        LruCache {
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            on_evict: None,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl<K, V> Cache for LruCache<K, V>
+where
+    K: Eq + Hash + Clone,
+{
+    type Key = K;
+    type Val = V;
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        let (val, used) = self.entries.get_mut(key)?;
+        self.tick += 1;
+        self.recency.remove(used);
+        *used = self.tick;
+        self.recency.insert(self.tick, key.clone());
+        Some(val)
+    }
+
+    fn insert(&mut self, key: K, val: V) {
+        self.tick += 1;
+        if let Some((_, used)) = self.entries.insert(key.clone(), (val, self.tick)) {
+            self.recency.remove(&used);
+        }
+        self.recency.insert(self.tick, key);
+        while self.entries.len() > self.capacity {
+            let (_, oldest) = self.recency.pop_first().unwrap();
+            let (val, _) = self.entries.remove(&oldest).unwrap();
+            if let Some(on_evict) = &self.on_evict {
+                on_evict(&oldest, &val);
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        let (val, used) = self.entries.remove(key)?;
+        self.recency.remove(&used);
+        Some(val)
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    fn retain<P: FnMut(&K, &V) -> bool>(&mut self, mut pred: P) {
+        let recency = &mut self.recency;
+        self.entries.retain(|k, (v, used)| {
+            let keep = pred(k, v);
+            if !keep {
+                recency.remove(used);
+            }
+            keep
+        });
+    }
+
+    fn set_on_evict(&mut self, on_evict: EvictFn<K, V>) {
+        self.on_evict = Some(on_evict);
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.entries.reserve(additional.min(self.capacity));
+    }
+}
+
+/// Holds up to `max_entries` entries, evicting the oldest inserted one to make room. Lookups
+/// don't affect the order, and replacing a value keeps its key's place. Evicted entries are
+/// reported through [`Cache::set_on_evict`].
+pub struct BoundedCache<K, V> {
+    max_entries: usize,
+    // entries with the sequence number they were inserted at
+    entries: HashMap<K, (V, u64)>,
+    // keys in insertion order, oldest first. Removed keys stay until they reach the front, and
+    // are recognized by a sequence number that no longer matches their entry's.
+    order: VecDeque<(u64, K)>,
+    seq: u64,
+    on_evict: Option<EvictFn<K, V>>,
+}
+
+impl<K, V> BoundedCache<K, V>
+where
+    K: Eq + Hash,
+{
+    /// Creates an empty cache holding at most `max_entries` entries, and at least one.
+    pub fn new(max_entries: usize) -> Self {
+        BoundedCache {
+            max_entries: max_entries.max(1),
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            seq: 0,
+            on_evict: None,
+        }
+    }
+
+    fn is_live(&self, seq: u64, key: &K) -> bool {
+        self.entries.get(key).is_some_and(|(_, s)| *s == seq)
+    }
+
+    pub fn max_entries(&self) -> usize {
+        self.max_entries
+    }
+}
+
+impl<K, V> Cache for BoundedCache<K, V>
+where
+    K: Eq + Hash + Clone,
+{
+    type Key = K;
+    type Val = V;
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.entries.get(key).map(|(v, _)| v)
+    }
+
+    fn insert(&mut self, key: K, val: V) {
+        if let Some((v, _)) = self.entries.get_mut(&key) {
+            *v = val;
+            return;
+        }
+        self.seq += 1;
+        self.entries.insert(key.clone(), (val, self.seq));
+        self.order.push_back((self.seq, key));
+        while self.entries.len() > self.max_entries {
+            let (seq, oldest) = self.order.pop_front().unwrap();
+            if !self.is_live(seq, &oldest) {
+                continue;
+            }
+            let (val, _) = self.entries.remove(&oldest).unwrap();
+            if let Some(on_evict) = &self.on_evict {
+                on_evict(&oldest, &val);
+            }
+        }
+        // keys removed without being evicted would pile up otherwise
+        if self.order.len() > 2 * self.max_entries {
+            let entries = &self.entries;
+            self.order
+                .retain(|(seq, k)| entries.get(k).is_some_and(|(_, s)| s == seq));
+        }
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.entries.remove(key).map(|(v, _)| v)
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    fn retain<P: FnMut(&K, &V) -> bool>(&mut self, mut pred: P) {
+        self.entries.retain(|k, (v, _)| pred(k, v));
+    }
+
+    fn set_on_evict(&mut self, on_evict: EvictFn<K, V>) {
+        self.on_evict = Some(on_evict);
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        let additional = additional.min(self.max_entries);
+        self.entries.reserve(additional);
+        self.order.reserve(additional);
+    }
+}
+
+/// Expires every entry a fixed `ttl` after it was inserted. Expired entries are dropped lazily
+/// when looked up, or all at once by [`TtlCache::purge_expired`], and reported through
+/// [`Cache::set_on_evict`] either way. A `ttl` too large to add to the current time, such as
+/// `Duration::MAX`, never expires.
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    // `None` for a `ttl` too large to add to the insert time
+    entries: HashMap<K, (V, Option<Instant>)>,
+    on_evict: Option<EvictFn<K, V>>,
+}
+
+impl<K, V> TtlCache<K, V>
+where
+    K: Eq + Hash,
+{
+    pub fn new(ttl: Duration) -> Self {
+        TtlCache {
+            ttl,
+            entries: HashMap::new(),
+            on_evict: None,
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Drops every expired entry, reclaiming its memory without waiting for a lookup.
+    pub fn purge_expired(&mut self) {
+        let now = Instant::now();
+        let on_evict = &self.on_evict;
+        self.entries.retain(|k, (v, expires_at)| {
+            let live = !expired(*expires_at, now);
+            if !live {
+                if let Some(on_evict) = on_evict {
+                    on_evict(k, v);
+                }
+            }
+            live
+        });
+    }
+}
+
+fn expired(expires_at: Option<Instant>, now: Instant) -> bool {
+    expires_at.is_some_and(|at| at <= now)
+}
+
+impl<K, V> Cache for TtlCache<K, V>
+where
+    K: Eq + Hash,
+{
+    type Key = K;
+    type Val = V;
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        if expired(self.entries.get(key)?.1, Instant::now()) {
+            let (k, (v, _)) = self.entries.remove_entry(key).unwrap();
+            if let Some(on_evict) = &self.on_evict {
+                on_evict(&k, &v);
+            }
+            return None;
+        }
+        self.entries.get(key).map(|(v, _)| v)
+    }
+
+    fn insert(&mut self, key: K, val: V) {
+        let expires_at = Instant::now().checked_add(self.ttl);
+        self.entries.insert(key, (val, expires_at));
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.entries.remove(key).map(|(v, _)| v)
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    // includes expired entries not yet purged
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        matches!(self.entries.get(key), Some((_, expires_at)) if !expired(*expires_at, Instant::now()))
+    }
+
+    fn retain<P: FnMut(&K, &V) -> bool>(&mut self, mut pred: P) {
+        self.entries.retain(|k, (v, _)| pred(k, v));
+    }
+
+    fn set_on_evict(&mut self, on_evict: EvictFn<K, V>) {
+        self.on_evict = Some(on_evict);
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.entries.reserve(additional);
+    }
+}
+
+/// Composes a small, fast `l1` cache in front of a larger `l2` one, such as an in-process map in
+/// front of a shared store. Lookups try `l1` first and promote `l2` hits into it; inserts write
+/// through to both. [`Cache`] is synchronous and called under the loader's lock, so an external
+/// `l2` needs a blocking adapter and holds up every load while it runs.
+///
+/// `l1` may evict entries `l2` still holds, so [`Cache::len`] reports `l2`, and only `l2`
+/// evictions reach [`Cache::set_on_evict`].
+pub struct TieredCache<C1, C2> {
+    l1: C1,
+    l2: C2,
+}
+
+impl<C1, C2> TieredCache<C1, C2> {
+    pub fn new(l1: C1, l2: C2) -> Self {
+        TieredCache { l1, l2 }
+    }
+
+    pub fn l1(&self) -> &C1 {
+        &self.l1
+    }
+
+    pub fn l2(&self) -> &C2 {
+        &self.l2
+    }
+}
+
+impl<K, V, C1, C2> Cache for TieredCache<C1, C2>
+where
+    K: Clone,
+    V: Clone,
+    C1: Cache<Key = K, Val = V>,
+    C2: Cache<Key = K, Val = V>,
+{
+    type Key = K;
+    type Val = V;
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        if !self.l1.contains_key(key) {
+            let val = self.l2.get(key)?.clone();
+            self.l1.insert(key.clone(), val);
+            // e.g. an `l1` whose TTL has already passed doesn't keep the promoted entry
+            if !self.l1.contains_key(key) {
+                return self.l2.get(key);
+            }
+        }
+        self.l1.get(key)
+    }
+
+    fn insert(&mut self, key: K, val: V) {
+        self.l1.insert(key.clone(), val.clone());
+        self.l2.insert(key, val);
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        let l1 = self.l1.remove(key);
+        self.l2.remove(key).or(l1)
+    }
+
+    fn clear(&mut self) {
+        self.l1.clear();
+        self.l2.clear();
+    }
+
+    fn len(&self) -> usize {
+        self.l2.len()
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.l1.contains_key(key) || self.l2.contains_key(key)
+    }
+
+    fn retain<P: FnMut(&K, &V) -> bool>(&mut self, mut pred: P) {
+        self.l1.retain(&mut pred);
+        self.l2.retain(pred);
+    }
+
+    fn set_on_evict(&mut self, on_evict: EvictFn<K, V>) {
+        self.l2.set_on_evict(on_evict);
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.l1.reserve(additional);
+        self.l2.reserve(additional);
+    }
+}
+
+/// Wraps a cache to call `on_drop` for every entry it stops holding: evicted by the inner cache,
+/// removed, replaced by a new value for its key, or cleared. Meant for values that own external
+/// resources, such as file handles, that need cleaning up however they leave the cache.
+pub struct CallbackCache<C: Cache> {
+    inner: C,
+    on_drop: std::sync::Arc<DropFn<C::Key, C::Val>>,
+}
+
+type DropFn<K, V> = dyn Fn(&K, &V) + Send + Sync;
+
+impl<C> CallbackCache<C>
+where
+    C: Cache,
+    C::Key: 'static,
+    C::Val: 'static,
+{
+    pub fn new(mut inner: C, on_drop: impl Fn(&C::Key, &C::Val) + Send + Sync + 'static) -> Self {
+        let on_drop: std::sync::Arc<DropFn<C::Key, C::Val>> = std::sync::Arc::new(on_drop);
+        inner.set_on_evict(Box::new({
+            let on_drop = on_drop.clone();
+            move |k, v| on_drop(k, v)
+        }));
+        CallbackCache { inner, on_drop }
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+}
+
+impl<C> Cache for CallbackCache<C>
+where
+    C: Cache,
+    C::Key: 'static,
+    C::Val: Clone + 'static,
+{
+    type Key = C::Key;
+    type Val = C::Val;
+
+    fn get(&mut self, key: &C::Key) -> Option<&C::Val> {
+        self.inner.get(key)
+    }
+
+    fn insert(&mut self, key: C::Key, val: C::Val) {
+        // replaced in place, so the inner cache keeps the key's place in its eviction order
+        if let Some(old) = self.inner.get(&key).cloned() {
+            (self.on_drop)(&key, &old);
+        }
+        self.inner.insert(key, val);
+    }
+
+    fn remove(&mut self, key: &C::Key) -> Option<C::Val> {
+        let val = self.inner.remove(key)?;
+        (self.on_drop)(key, &val);
+        Some(val)
+    }
+
+    fn clear(&mut self) {
+        let on_drop = &self.on_drop;
+        self.inner.retain(|k, v| {
+            on_drop(k, v);
+            false
+        });
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn contains_key(&self, key: &C::Key) -> bool {
+        self.inner.contains_key(key)
+    }
+
+    fn retain<P: FnMut(&C::Key, &C::Val) -> bool>(&mut self, mut pred: P) {
+        let on_drop = &self.on_drop;
+        self.inner.retain(|k, v| {
+            let keep = pred(k, v);
+            if !keep {
+                on_drop(k, v);
+            }
+            keep
+        });
+    }
+
+    // the inner cache's evictions are reported to both hooks
+    fn set_on_evict(&mut self, on_evict: EvictFn<C::Key, C::Val>) {
+        let on_drop = self.on_drop.clone();
+        self.inner.set_on_evict(Box::new(move |k, v| {
+            on_drop(k, v);
+            on_evict(k, v);
+        }));
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.inner.reserve(additional);
+    }
+}
+
+/// A sharded concurrent map, for loaders whose cache hits contend on the loader's lock. Through
+/// [`Cache::concurrent_reader`], [`crate::cached::Loader::try_load`] serves hits straight from
+/// the map; misses still queue under the lock. Never evicts. The loader keeps reading the map it
+/// was built with, so clear the cache rather than swap in a new one.
+#[cfg(feature = "dashmap")]
+pub struct DashMapCache<K, V> {
+    map: std::sync::Arc<dashmap::DashMap<K, V>>,
+    // the map only lends values behind a shard guard, so `get` lends a copy kept here instead
+    last: Option<V>,
+}
+
+#[cfg(feature = "dashmap")]
+impl<K, V> DashMapCache<K, V>
+where
+    K: Eq + Hash,
+{
+    pub fn new() -> Self {
+        DashMapCache {
+            map: Default::default(),
+            last: None,
+        }
+    }
+}
+
+#[cfg(feature = "dashmap")]
+impl<K, V> Default for DashMapCache<K, V>
+where
+    K: Eq + Hash,
+{
+    fn default() -> Self {
+        DashMapCache::new()
+    }
+}
+
+#[cfg(feature = "dashmap")]
+impl<K, V> Cache for DashMapCache<K, V>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    type Key = K;
+    type Val = V;
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.last = self.map.get(key).map(|v| v.clone());
+        self.last.as_ref()
+    }
+
+    fn insert(&mut self, key: K, val: V) {
+        self.map.insert(key, val);
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.last = None;
+        self.map.remove(key).map(|(_, v)| v)
+    }
+
+    fn clear(&mut self) {
+        self.last = None;
+        self.map.clear();
+    }
+
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.map.contains_key(key)
+    }
+
+    fn retain<P: FnMut(&K, &V) -> bool>(&mut self, mut pred: P) {
+        self.last = None;
+        self.map.retain(|k, v| pred(k, v));
+    }
+
+    fn concurrent_reader(&self) -> Option<crate::cached::ReadFn<K, V>> {
+        let map = self.map.clone();
+        Some(Box::new(move |key| map.get(key).map(|v| v.clone())))
+    }
+}
@@ -1,10 +1,29 @@
-use crate::runtime::{Arc, Mutex};
-use crate::{yield_fn, BatchFn, WaitForWorkFn};
+use crate::adaptive::AdaptiveBatchSize;
+use crate::diagnostics::UnbatchedMonitor;
+use crate::event::Event;
+use crate::instrument;
+use crate::mapped;
+use crate::rate_limit::RateLimiter;
+use crate::runtime::{self, Arc, Mutex, MutexGuard};
+use crate::semaphore::{Permit, Semaphore};
+use crate::stats::{Counters, LoaderStats, RecentStats, WindowedStats};
+use crate::wait::{notified, sleep_fn, yield_fn, Notify};
+use crate::{BatchFn, LoadError, WaitForWorkFn, DEFAULT_MAX_BATCH_SIZE, DEFAULT_YIELD_COUNT};
 use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
+use std::future::Future;
 use std::hash::{BuildHasher, Hash};
 use std::io::{Error, ErrorKind};
 use std::iter::IntoIterator;
+use std::panic::AssertUnwindSafe;
+use std::task::Poll;
+use std::time::{Duration, Instant};
+
+/// Called with each entry a cache drops on its own, see [`Cache::set_on_evict`].
+pub type EvictFn<K, V> = Box<dyn Fn(&K, &V) + Send + Sync>;
+
+/// Looks up a cached value without the loader's lock, see [`Cache::concurrent_reader`].
+pub type ReadFn<K, V> = Box<dyn Fn(&K) -> Option<V> + Send + Sync>;
 
 pub trait Cache {
     type Key;
@@ -13,6 +32,39 @@ pub trait Cache {
     fn insert(&mut self, key: Self::Key, val: Self::Val);
     fn remove(&mut self, key: &Self::Key) -> Option<Self::Val>;
     fn clear(&mut self);
+    fn len(&self) -> usize;
+
+    /// Whether a lookup of `key` would hit, without counting as a use of the entry.
+    fn contains_key(&self, key: &Self::Key) -> bool;
+
+    /// Keeps only the entries `pred` accepts. Dropped entries are not evictions. Not available
+    /// on `dyn Cache`, which stays object-safe.
+    fn retain<P: FnMut(&Self::Key, &Self::Val) -> bool>(&mut self, pred: P)
+    where
+        Self: Sized;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Installs a hook that bounded caches call for every entry they evict to make room or
+    /// because it expired. Entries removed through [`Cache::remove`] or [`Cache::clear`] are not
+    /// evictions. The default ignores the hook, which suits caches that never evict.
+    fn set_on_evict(&mut self, _on_evict: EvictFn<Self::Key, Self::Val>) {}
+
+    /// A reader for caches that support concurrent lookups, which the loader then uses to serve
+    /// cache hits in [`Loader::try_load`] without taking its lock. It must see every change made
+    /// through the cache itself. The loader asks for it once, when built, and ignores it while
+    /// [`Loader::with_value_ttl`] is set. Replacing the cache through [`Loader::with_cache_mut`]
+    /// leaves the reader on the old one, so such caches must be cleared or edited in place
+    /// instead. The default is `None`.
+    fn concurrent_reader(&self) -> Option<ReadFn<Self::Key, Self::Val>> {
+        None
+    }
+
+    /// Makes room for at least `additional` more entries up front, a hint that may be ignored.
+    /// The default does nothing.
+    fn reserve(&mut self, _additional: usize) {}
 }
 
 impl<K, V, S: BuildHasher> Cache for HashMap<K, V, S>
@@ -41,6 +93,26 @@ where
     fn clear(&mut self) {
         HashMap::clear(self)
     }
+
+    #[inline]
+    fn len(&self) -> usize {
+        HashMap::len(self)
+    }
+
+    #[inline]
+    fn contains_key(&self, key: &K) -> bool {
+        HashMap::contains_key(self, key)
+    }
+
+    #[inline]
+    fn retain<P: FnMut(&K, &V) -> bool>(&mut self, mut pred: P) {
+        HashMap::retain(self, |k, v| pred(k, v))
+    }
+
+    #[inline]
+    fn reserve(&mut self, additional: usize) {
+        HashMap::reserve(self, additional)
+    }
 }
 
 struct State<K, V, C = HashMap<K, V>>
@@ -49,20 +121,500 @@ where
 {
     completed: C,
     pending: HashSet<K>,
+    // pending keys queued only by `try_load_with_cancel` callers, with how many still wait
+    cancellable: HashMap<K, usize>,
+    loading: HashMap<K, Arc<Batch<K, V>>>,
+    // the batch the pending keys will be dispatched in
+    queued: Arc<Batch<K, V>>,
+    expiry: HashMap<K, Instant>,
+    on_evict: Option<Arc<EvictFn<K, V>>>,
+    on_resolved: HashMap<K, Vec<ResolvedFn<K, V>>>,
+    #[cfg(feature = "testing")]
+    next_batch: Arc<Event<BatchEvent<K>>>,
 }
 
-impl<K: Eq + Hash, V, C> State<K, V, C>
+impl<K: Eq + Hash, V: Clone, C> State<K, V, C>
 where
     C: Cache<Key = K, Val = V>,
 {
+    // Queues `key` unless it is already queued or in flight, and returns the batch carrying it.
+    fn enqueue(&mut self, key: &K) -> Arc<Batch<K, V>>
+    where
+        K: Clone,
+    {
+        if let Some(batch) = self.loading.get(key) {
+            return batch.clone();
+        }
+        // a caller that can't cancel needs the key dispatched either way
+        self.cancellable.remove(key);
+        if !self.pending.contains(key) {
+            self.pending.insert(key.clone());
+        }
+        self.queued.clone()
+    }
+
+    // Like `enqueue`, but the key can be withdrawn by `cancel` unless another caller needs it.
+    fn enqueue_cancellable(&mut self, key: &K) -> Arc<Batch<K, V>>
+    where
+        K: Clone,
+    {
+        if let Some(batch) = self.loading.get(key) {
+            return batch.clone();
+        }
+        if !self.pending.contains(key) {
+            self.pending.insert(key.clone());
+            self.cancellable.insert(key.clone(), 1);
+        } else if let Some(waiting) = self.cancellable.get_mut(key) {
+            *waiting += 1;
+        }
+        self.queued.clone()
+    }
+
+    // Withdraws a cancelled caller's interest in `key`, unqueueing it if no one else waits on it.
+    fn cancel(&mut self, key: &K) {
+        if let Some(waiting) = self.cancellable.get_mut(key) {
+            *waiting -= 1;
+            if *waiting == 0 {
+                self.cancellable.remove(key);
+                self.pending.remove(key);
+            }
+        }
+    }
+
+    // Caches a value supplied by the caller rather than the batch function.
+    fn prime(&mut self, key: K, val: V, expires_at: Option<Instant>)
+    where
+        K: Clone,
+    {
+        self.discard_loading(&key);
+        if !self.on_resolved.is_empty() {
+            self.resolved(&key, &Ok(val.clone()));
+        }
+        self.insert(key, val, expires_at);
+    }
+
+    // Fires the `on_resolved` callbacks registered for `key`.
+    fn resolved(&mut self, key: &K, result: &Result<V, LoadError<K>>) {
+        for callback in self.on_resolved.remove(key).into_iter().flatten() {
+            callback(result);
+        }
+    }
+
+    // Stops an in-flight batch from caching its result for `key`, e.g. because the key was
+    // invalidated or primed while the batch was running.
+    fn discard_loading(&mut self, key: &K)
+    where
+        K: Clone,
+    {
+        if let Some(batch) = self.loading.remove(key) {
+            batch.discarded.lock().unwrap().insert(key.clone());
+        }
+    }
+
+    // Looks up a cached value, evicting it instead if it has expired.
+    fn get(&mut self, key: &K) -> Option<&V> {
+        if let Some(expires_at) = self.expiry.get(key) {
+            if *expires_at <= Instant::now() {
+                self.expiry.remove(key);
+                if let (Some(val), Some(on_evict)) = (self.completed.remove(key), &self.on_evict) {
+                    on_evict(key, &val);
+                }
+                return None;
+            }
+        }
+        self.completed.get(key)
+    }
+
+    // Like `get`, but leaves an expired value in place for the next lookup to evict.
+    fn contains_key(&self, key: &K) -> bool {
+        let expired =
+            matches!(self.expiry.get(key), Some(expires_at) if *expires_at <= Instant::now());
+        !expired && self.completed.contains_key(key)
+    }
+
+    fn insert(&mut self, key: K, val: V, expires_at: Option<Instant>)
+    where
+        K: Clone,
+    {
+        match expires_at {
+            Some(expires_at) => self.expiry.insert(key.clone(), expires_at),
+            None => self.expiry.remove(&key),
+        };
+        self.completed.insert(key, val);
+    }
+
+    fn remove(&mut self, key: &K) {
+        self.expiry.remove(key);
+        self.completed.remove(key);
+    }
+
+    fn clear(&mut self) {
+        self.expiry.clear();
+        self.completed.clear();
+    }
+
+    fn retain(&mut self, mut pred: impl FnMut(&K, &V) -> bool)
+    where
+        K: Clone,
+    {
+        let mut dropped = Vec::new();
+        self.completed.retain(|k, v| {
+            let keep = pred(k, v);
+            if !keep {
+                dropped.push(k.clone());
+            }
+            keep
+        });
+        for k in dropped.iter() {
+            self.expiry.remove(k);
+        }
+    }
+
     fn with_cache(cache: C) -> Self {
         State {
             completed: cache,
             pending: HashSet::new(),
+            cancellable: HashMap::new(),
+            loading: HashMap::new(),
+            queued: Arc::new(Batch::new()),
+            expiry: HashMap::new(),
+            on_evict: None,
+            on_resolved: HashMap::new(),
+            #[cfg(feature = "testing")]
+            next_batch: Arc::new(Event::new()),
+        }
+    }
+}
+
+/// A batch handed to the [`BatchFn`], as observed by [`Loader::next_batch`].
+#[cfg(feature = "testing")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchEvent<K> {
+    pub keys: Vec<K>,
+}
+
+/// Keys a batch function failed to return while [`Loader::with_require_all_keys`] is on,
+/// reported as [`LoadError::Integrity`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntegrityError<K> {
+    /// Every key of the batch that the batch function left unresolved.
+    pub missing: Vec<K>,
+}
+
+impl<K: Debug> std::fmt::Display for IntegrityError<K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "batch function did not return keys: {:?}", self.missing)
+    }
+}
+
+impl<K: Debug> std::error::Error for IntegrityError<K> {}
+
+/// How [`Loader::merge_from`] treats keys cached by both loaders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Keep this loader's value.
+    KeepExisting,
+    /// Replace it with the other loader's value.
+    Overwrite,
+}
+
+/// Settings for [`Loader::with_cache_and_config`], as a plain value that can be built from
+/// deserialized settings. Start from `LoaderConfig::default()`, which matches [`Loader::new`],
+/// and set options with the `with_*` methods or by assigning fields; new options may be added
+/// in minor releases.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LoaderConfig {
+    /// See [`Loader::with_max_batch_size`]. `0` dispatches every key in a batch of its own.
+    pub max_batch_size: usize,
+    /// `(min, max)` for [`Loader::with_adaptive_batch_size`], replacing `max_batch_size`.
+    pub adaptive_batch_size: Option<(usize, usize)>,
+    /// See [`Loader::with_yield_count`]. Can't be combined with `wait_duration`.
+    pub yield_count: Option<usize>,
+    /// See [`Loader::with_batch_window`]. Can't be combined with `yield_count`.
+    pub wait_duration: Option<Duration>,
+    /// See [`Loader::with_max_keys_per_call`].
+    pub max_keys_per_call: Option<usize>,
+}
+
+impl Default for LoaderConfig {
+    fn default() -> Self {
+        LoaderConfig {
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            adaptive_batch_size: None,
+            yield_count: None,
+            wait_duration: None,
+            max_keys_per_call: None,
+        }
+    }
+}
+
+impl LoaderConfig {
+    pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = max_batch_size;
+        self
+    }
+
+    pub fn with_adaptive_batch_size(mut self, min: usize, max: usize) -> Self {
+        self.adaptive_batch_size = Some((min, max));
+        self
+    }
+
+    pub fn with_yield_count(mut self, yield_count: usize) -> Self {
+        self.yield_count = Some(yield_count);
+        self
+    }
+
+    pub fn with_batch_window(mut self, wait_duration: Duration) -> Self {
+        self.wait_duration = Some(wait_duration);
+        self
+    }
+
+    pub fn with_max_keys_per_call(mut self, max_keys_per_call: usize) -> Self {
+        self.max_keys_per_call = Some(max_keys_per_call);
+        self
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        let invalid = |msg: &str| Err(Error::new(ErrorKind::InvalidInput, msg.to_string()));
+        if let Some((min, max)) = self.adaptive_batch_size {
+            if min == 0 || min > max {
+                return invalid("adaptive_batch_size needs 1 <= min <= max");
+            }
+        }
+        if self.yield_count.is_some() && self.wait_duration.is_some() {
+            return invalid("yield_count and wait_duration are mutually exclusive");
+        }
+        Ok(())
+    }
+}
+
+/// Builds a [`Loader`] through the same `with_*` chain, but rejects setting more than one wait
+/// strategy, where the loader's own methods would silently keep the last one. Settings the
+/// builder doesn't mirror can be applied with [`LoaderBuilder::configure`].
+pub struct LoaderBuilder<K, V, F, C = HashMap<K, V>>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: BatchFn<K, V>,
+    C: Cache<Key = K, Val = V>,
+{
+    loader: Loader<K, V, F, C>,
+    wait_strategies: Vec<&'static str>,
+}
+
+impl<K, V, F, C> LoaderBuilder<K, V, F, C>
+where
+    K: Eq + Hash + Clone + Debug,
+    V: Clone,
+    F: BatchFn<K, V>,
+    C: Cache<Key = K, Val = V>,
+{
+    pub fn with_cache(load_fn: F, cache: C) -> Self {
+        LoaderBuilder {
+            loader: Loader::with_cache(load_fn, cache),
+            wait_strategies: Vec::new(),
+        }
+    }
+
+    pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.loader = self.loader.with_max_batch_size(max_batch_size);
+        self
+    }
+
+    /// See [`Loader::with_yield_count`]. A wait strategy.
+    pub fn with_yield_count(self, yield_count: usize) -> Self {
+        self.wait_strategy("yield_count", |l| l.with_yield_count(yield_count))
+    }
+
+    /// See [`Loader::with_custom_wait_for_work`]. A wait strategy.
+    pub fn with_custom_wait_for_work(self, wait_for_work_fn: impl WaitForWorkFn) -> Self {
+        self.wait_strategy("custom_wait_for_work", |l| {
+            l.with_custom_wait_for_work(wait_for_work_fn)
+        })
+    }
+
+    /// See [`Loader::with_batch_window`]. A wait strategy.
+    pub fn with_batch_window(self, window: Duration) -> Self {
+        self.wait_strategy("batch_window", |l| l.with_batch_window(window))
+    }
+
+    /// See [`Loader::with_dispatch_notifier`]. A wait strategy.
+    pub fn with_dispatch_notifier(self, notify: Arc<Notify>) -> Self {
+        self.wait_strategy("dispatch_notifier", |l| l.with_dispatch_notifier(notify))
+    }
+
+    /// See [`Loader::with_batch_trigger`]. A wait strategy.
+    pub fn with_batch_trigger(self, min_keys: usize, max_delay: Duration) -> Self {
+        self.wait_strategy("batch_trigger", |l| {
+            l.with_batch_trigger(min_keys, max_delay)
+        })
+    }
+
+    /// Applies any other loader setting, e.g. `|l| l.with_value_ttl(ttl_fn)`.
+    pub fn configure(mut self, f: impl FnOnce(Loader<K, V, F, C>) -> Loader<K, V, F, C>) -> Self {
+        self.loader = f(self.loader);
+        self
+    }
+
+    /// Returns the loader, or an `InvalidInput` error naming the conflicting wait strategies.
+    pub fn build(self) -> Result<Loader<K, V, F, C>, Error> {
+        if self.wait_strategies.len() > 1 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "wait strategies are mutually exclusive: {}",
+                    self.wait_strategies.join(", ")
+                ),
+            ));
+        }
+        Ok(self.loader)
+    }
+
+    fn wait_strategy(
+        mut self,
+        name: &'static str,
+        f: impl FnOnce(Loader<K, V, F, C>) -> Loader<K, V, F, C>,
+    ) -> Self {
+        self.wait_strategies.push(name);
+        self.configure(f)
+    }
+}
+
+/// Outcome of [`Loader::prime_many`], sorting the primed keys by what was cached before the
+/// call. A key primed more than once in one call is sorted by its first value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrimeSummary<K: Eq + Hash> {
+    /// Keys that were not cached before.
+    pub inserted: HashSet<K>,
+    /// Keys whose cached value differed and was replaced.
+    pub overwritten: HashSet<K>,
+    /// Keys already cached with an equal value.
+    pub unchanged: HashSet<K>,
+}
+
+// A dispatched batch. Its keys map to it in `State::loading` until its results are cached, and
+// `done` is set once that has happened or the dispatching future was dropped mid-flight.
+struct Batch<K, V> {
+    done: Event<()>,
+    discarded: std::sync::Mutex<HashSet<K>>,
+    results: std::sync::Mutex<HashMap<K, V>>,
+    // requested keys the batch function didn't return, if checked by `with_require_all_keys`
+    missing: std::sync::Mutex<Vec<K>>,
+    // the keys of a batch function call that panicked
+    panicked: std::sync::Mutex<Vec<K>>,
+}
+
+impl<K: Eq + Hash, V: Clone> Batch<K, V> {
+    fn new() -> Self {
+        Batch {
+            done: Event::new(),
+            discarded: std::sync::Mutex::new(HashSet::new()),
+            results: std::sync::Mutex::new(HashMap::new()),
+            missing: std::sync::Mutex::new(Vec::new()),
+            panicked: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    fn result(&self, key: &K) -> Option<V> {
+        self.results.lock().unwrap().get(key).cloned()
+    }
+}
+
+// Runs `fut` to completion, or returns `None` once `cancel` completes first.
+async fn unless_cancelled<T>(
+    fut: impl Future<Output = T>,
+    mut cancel: std::pin::Pin<&mut impl Future<Output = ()>>,
+) -> Option<T> {
+    let mut fut = std::pin::pin!(fut);
+    std::future::poll_fn(|cx| {
+        if let Poll::Ready(v) = fut.as_mut().poll(cx) {
+            Poll::Ready(Some(v))
+        } else if cancel.as_mut().poll(cx).is_ready() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+// Runs `fut` to completion, or returns `None` if it panics.
+async fn unless_panicked<T>(fut: impl Future<Output = T>) -> Option<T> {
+    let mut fut = std::pin::pin!(fut);
+    std::future::poll_fn(|cx| {
+        match std::panic::catch_unwind(AssertUnwindSafe(|| fut.as_mut().poll(cx))) {
+            Ok(Poll::Ready(v)) => Poll::Ready(Some(v)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(_) => Poll::Ready(None),
+        }
+    })
+    .await
+}
+
+// Marks a batch done however its dispatch ends, so waiters never hang on a dropped dispatcher.
+struct SetOnDrop<'a, K, V>(&'a Batch<K, V>);
+
+impl<K, V> Drop for SetOnDrop<'_, K, V> {
+    fn drop(&mut self) {
+        self.0.done.set(());
+    }
+}
+
+type ResolvedFn<K, V> = Box<dyn FnOnce(&Result<V, LoadError<K>>) + Send>;
+
+type ValueTtlFn<V> = dyn Fn(&V) -> Option<Duration> + Send + Sync;
+
+type CacheIfFn<V> = dyn Fn(&V) -> bool + Send + Sync;
+
+type BatchFullFn = dyn Fn(usize) + Send + Sync;
+
+struct Retry<V> {
+    max_retries: usize,
+    backoff: Duration,
+    retryable: Box<dyn Fn(&V) -> bool + Send + Sync>,
+}
+
+// Settings a builder method applies to the state, kept until the state is next locked if
+// another handle holds it.
+struct Setup<K, V> {
+    reserve: usize,
+    on_evict: Option<Arc<EvictFn<K, V>>>,
+    // the hook handed to the cache, built where `'static` is known
+    cache_on_evict: Option<EvictFn<K, V>>,
+}
+
+impl<K, V> Default for Setup<K, V> {
+    fn default() -> Self {
+        Setup {
+            reserve: 0,
+            on_evict: None,
+            cache_on_evict: None,
         }
     }
 }
 
+struct DryRun<K, V> {
+    default_fn: Box<dyn Fn(&K) -> V + Send + Sync>,
+    batches: std::sync::Mutex<Vec<Vec<K>>>,
+}
+
+/// Coalesces concurrent loads into batches for a [`BatchFn`] and caches the results.
+///
+/// Keys and values don't need to be `'static`: a loader over data borrowed from the current
+/// scope, such as `Loader<&'a str, &'a Row, F>`, works as long as it is driven within that scope
+/// (for example with `join` or a scoped executor) rather than moved into a spawned task.
+///
+/// Every hit clones the cached value, and a batch's results are briefly held twice while they
+/// are handed to waiters. For large payloads cache a cheap handle instead, such as
+/// `Arc<Payload>` or a struct holding metadata plus what is needed to fetch or stream the body
+/// on demand, so the cache only keeps the handles. See `examples/payload_handle.rs`, and
+/// [`crate::arc_batch_fn`] to wrap an existing batch function's values in `Arc`.
+///
+/// Loads fail with a [`LoadError`] carrying the keys involved, which converts into an
+/// [`std::io::Error`] for callers that need one. Backend errors are not part of it: a batch
+/// function reports them in its values, e.g. `V = Result<Row, DbError>` as with
+/// [`crate::indexed_batch_fn`], so callers can match on them directly.
 pub struct Loader<K, V, F, C = HashMap<K, V>>
 where
     K: Eq + Hash + Clone,
@@ -71,9 +623,30 @@ where
     C: Cache<Key = K, Val = V>,
 {
     state: Arc<Mutex<State<K, V, C>>>,
+    setup: Arc<std::sync::Mutex<Setup<K, V>>>,
     load_fn: Arc<Mutex<F>>,
+    reader: Option<Arc<ReadFn<K, V>>>,
     wait_for_work_fn: Arc<dyn WaitForWorkFn>,
+    load_many_wait_fn: Option<Arc<dyn WaitForWorkFn>>,
     max_batch_size: usize,
+    adaptive: Option<Arc<AdaptiveBatchSize>>,
+    dry_run: Option<Arc<DryRun<K, V>>>,
+    clone_load_fn: Option<fn(&F) -> F>,
+    sort_keys: Option<fn(&mut [K])>,
+    max_keys_per_call: Option<usize>,
+    max_pending: Option<usize>,
+    value_ttl: Option<Arc<ValueTtlFn<V>>>,
+    cache_if: Option<Arc<CacheIfFn<V>>>,
+    on_batch_full: Option<Arc<BatchFullFn>>,
+    trigger_keys: Option<usize>,
+    require_all_keys: bool,
+    rate_limit: Option<Arc<RateLimiter>>,
+    batch_permits: Option<Arc<Semaphore>>,
+    retry: Option<Arc<Retry<V>>>,
+    unbatched: Option<Arc<UnbatchedMonitor>>,
+    recent_stats: Option<Arc<RecentStats>>,
+    counters: Arc<Counters>,
+    permanent: bool,
 }
 
 impl<K, V, F, C> Clone for Loader<K, V, F, C>
@@ -86,9 +659,30 @@ where
     fn clone(&self) -> Self {
         Loader {
             state: self.state.clone(),
+            setup: self.setup.clone(),
             max_batch_size: self.max_batch_size,
             load_fn: self.load_fn.clone(),
+            reader: self.reader.clone(),
             wait_for_work_fn: self.wait_for_work_fn.clone(),
+            load_many_wait_fn: self.load_many_wait_fn.clone(),
+            adaptive: self.adaptive.clone(),
+            dry_run: self.dry_run.clone(),
+            clone_load_fn: self.clone_load_fn,
+            sort_keys: self.sort_keys,
+            max_keys_per_call: self.max_keys_per_call,
+            max_pending: self.max_pending,
+            value_ttl: self.value_ttl.clone(),
+            cache_if: self.cache_if.clone(),
+            on_batch_full: self.on_batch_full.clone(),
+            trigger_keys: self.trigger_keys,
+            require_all_keys: self.require_all_keys,
+            rate_limit: self.rate_limit.clone(),
+            batch_permits: self.batch_permits.clone(),
+            retry: self.retry.clone(),
+            unbatched: self.unbatched.clone(),
+            recent_stats: self.recent_stats.clone(),
+            counters: self.counters.clone(),
+            permanent: self.permanent,
         }
     }
 }
@@ -103,6 +697,11 @@ where
     pub fn new(load_fn: F) -> Loader<K, V, F, HashMap<K, V>> {
         Loader::with_cache(load_fn, HashMap::new())
     }
+
+    /// Starts a [`LoaderBuilder`], which checks the wait strategy is set at most once.
+    pub fn builder(load_fn: F) -> LoaderBuilder<K, V, F, HashMap<K, V>> {
+        LoaderBuilder::with_cache(load_fn, HashMap::new())
+    }
 }
 
 impl<K, V, F, C> Loader<K, V, F, C>
@@ -114,163 +713,1368 @@ where
 {
     pub fn with_cache(load_fn: F, cache: C) -> Loader<K, V, F, C> {
         Loader {
+            reader: cache.concurrent_reader().map(Arc::new),
             state: Arc::new(Mutex::new(State::with_cache(cache))),
+            setup: Arc::default(),
             load_fn: Arc::new(Mutex::new(load_fn)),
-            max_batch_size: 200,
-            wait_for_work_fn: Arc::new(yield_fn(10)),
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            wait_for_work_fn: Arc::new(yield_fn(DEFAULT_YIELD_COUNT)),
+            load_many_wait_fn: None,
+            adaptive: None,
+            dry_run: None,
+            clone_load_fn: None,
+            sort_keys: None,
+            max_keys_per_call: None,
+            max_pending: None,
+            value_ttl: None,
+            cache_if: None,
+            on_batch_full: None,
+            trigger_keys: None,
+            require_all_keys: false,
+            rate_limit: None,
+            batch_permits: None,
+            retry: None,
+            unbatched: None,
+            recent_stats: None,
+            counters: Arc::default(),
+            permanent: false,
+        }
+    }
+
+    /// Builds a loader from a [`LoaderConfig`] in one go, rejecting invalid or conflicting
+    /// settings with an `InvalidInput` error.
+    pub fn with_cache_and_config(
+        load_fn: F,
+        cache: C,
+        config: LoaderConfig,
+    ) -> Result<Loader<K, V, F, C>, Error> {
+        config.validate()?;
+        let mut loader =
+            Loader::with_cache(load_fn, cache).with_max_batch_size(config.max_batch_size);
+        if let Some((min, max)) = config.adaptive_batch_size {
+            loader = loader.with_adaptive_batch_size(min, max);
+        }
+        if let Some(yield_count) = config.yield_count {
+            loader = loader.with_yield_count(yield_count);
         }
+        if let Some(duration) = config.wait_duration {
+            loader = loader.with_batch_window(duration);
+        }
+        loader.max_keys_per_call = config.max_keys_per_call;
+        Ok(loader)
     }
 
+    /// Caps the number of distinct keys per [`BatchFn::load`] call, e.g. for a backend's limit
+    /// on `IN` clause items. Loads of the same key share one slot, and pending keys beyond the
+    /// cap are sent in further batches. `0` turns batching off: every key is dispatched as soon
+    /// as it is queued, in a batch of its own.
     pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
         self.max_batch_size = max_batch_size;
+        self.adaptive = None;
         self
     }
 
-    pub fn with_yield_count(mut self, yield_count: usize) -> Self {
-        self.wait_for_work_fn = Arc::new(yield_fn(yield_count));
+    /// Lets the loader pick its early-flush batch size within `min..=max` from observed batch
+    /// latency, growing it while the batch function keeps up and shrinking it when batches slow
+    /// down. Replaces a fixed [`Self::with_max_batch_size()`]; [`Self::max_batch_size()`]
+    /// reports `max` and [`Self::current_batch_size()`] the size currently in effect.
+    pub fn with_adaptive_batch_size(mut self, min: usize, max: usize) -> Self {
+        let adaptive = AdaptiveBatchSize::new(min, max);
+        self.max_batch_size = adaptive.max();
+        self.adaptive = Some(Arc::new(adaptive));
         self
     }
 
-    /// Replaces the yielding for work behavior with an arbitrary future. Rather than yielding
-    /// the runtime repeatedly this will generate and `.await` a future of your choice.
-    /// ***This is incompatible with*** [`Self::with_yield_count()`].
-    pub fn with_custom_wait_for_work(mut self, wait_for_work_fn: impl WaitForWorkFn) -> Self {
-        self.wait_for_work_fn = Arc::new(wait_for_work_fn);
+    /// Calls `on_batch_full` with the number of queued keys whenever they reach the batch size and
+    /// are dispatched right away, e.g. to log or count batches that suggest the limit is too low.
+    pub fn with_on_batch_full(
+        mut self,
+        on_batch_full: impl Fn(usize) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_batch_full = Some(Arc::new(on_batch_full));
         self
     }
 
-    pub fn max_batch_size(&self) -> usize {
-        self.max_batch_size
+    /// Rejects [`Self::try_load_many`] calls with more than `max_keys` keys, before any of them
+    /// is queued. Unlike the batch size this bounds a single request, e.g. a list of ids taken
+    /// from client input.
+    pub fn with_max_keys_per_call(mut self, max_keys: usize) -> Self {
+        self.max_keys_per_call = Some(max_keys);
+        self
     }
 
-    pub async fn try_load(&self, key: K) -> Result<V, Error> {
-        let mut state = self.state.lock().await;
-        if let Some(v) = state.completed.get(&key) {
-            return Ok((*v).clone());
-        }
-
-        if !state.pending.contains(&key) {
-            state.pending.insert(key.clone());
-            if state.pending.len() >= self.max_batch_size {
-                let keys = state.pending.drain().collect::<Vec<K>>();
-                let mut load_fn = self.load_fn.lock().await;
-                let load_ret = load_fn.load(keys.as_ref()).await;
-                drop(load_fn);
-                for (k, v) in load_ret.into_iter() {
-                    state.completed.insert(k, v);
-                }
-                return state.completed.get(&key).cloned().ok_or(Error::new(
-                    ErrorKind::NotFound,
-                    format!("could not lookup result for given key: {:?}", key),
-                ));
-            }
-        }
-        drop(state);
+    /// Bounds the number of queued keys: once `n` are pending, a batch is dispatched right away,
+    /// even in the middle of queueing a `load_many`, and callers with new keys wait for room,
+    /// e.g. while a rate-limited batch waits for its turn. An `n` of 0 is treated as 1.
+    pub fn with_max_pending(mut self, n: usize) -> Self {
+        self.max_pending = Some(n.max(1));
+        self
+    }
 
-        (self.wait_for_work_fn)().await;
+    /// Expires each cached value after the time-to-live `ttl_fn` computes from it when it is
+    /// cached, e.g. from a token's own `expires_at`. `None` keeps the value until it is cleared.
+    /// An expired value is evicted on its next lookup and loaded again.
+    pub fn with_value_ttl(
+        mut self,
+        ttl_fn: impl Fn(&V) -> Option<Duration> + Send + Sync + 'static,
+    ) -> Self {
+        self.value_ttl = Some(Arc::new(ttl_fn));
+        self
+    }
 
-        let mut state = self.state.lock().await;
-        if let Some(v) = state.completed.get(&key) {
-            return Ok((*v).clone());
-        }
+    /// Only caches the loaded values `cache_if` accepts. The others still reach every caller
+    /// waiting on their batch, but the next load of the key asks the batch function again. For a
+    /// fallible batch function with `V = Result<T, E>`, `with_cache_if(Result::is_ok)` keeps
+    /// failures from being cached so a retry can succeed. Primed values are always cached.
+    pub fn with_cache_if(mut self, cache_if: impl Fn(&V) -> bool + Send + Sync + 'static) -> Self {
+        self.cache_if = Some(Arc::new(cache_if));
+        self
+    }
 
-        if !state.pending.is_empty() {
-            let keys = state.pending.drain().collect::<Vec<K>>();
-            let mut load_fn = self.load_fn.lock().await;
-            let load_ret = load_fn.load(keys.as_ref()).await;
-            drop(load_fn);
-            for (k, v) in load_ret.into_iter() {
-                state.completed.insert(k, v);
-            }
-        }
+    /// Calls `on_evict` for every entry the cache evicts on its own, and for values expired
+    /// through [`Self::with_value_ttl()`], e.g. to count evictions or re-warm hot keys. Explicit
+    /// clears are not reported. Caches that don't implement [`Cache::set_on_evict`] only report
+    /// expiries.
+    pub fn with_on_evict(self, on_evict: impl Fn(&K, &V) + Send + Sync + 'static) -> Self
+    where
+        K: 'static,
+        V: 'static,
+    {
+        let on_evict: Arc<EvictFn<K, V>> = Arc::new(Box::new(on_evict));
+        let hook = on_evict.clone();
+        self.configure_state(|setup| {
+            setup.on_evict = Some(on_evict);
+            setup.cache_on_evict = Some(Box::new(move |k, v| hook(k, v)));
+        })
+    }
 
-        state.completed.get(&key).cloned().ok_or(Error::new(
-            ErrorKind::NotFound,
-            format!("could not lookup result for given key: {:?}", key),
-        ))
+    /// A loader that hands out `f` applied to this loader's values, sharing its cache and
+    /// batches, e.g. for call sites that each need a different projection of a row. `f` runs on
+    /// every load, cache hits included.
+    pub fn map_value<W, G: Fn(V) -> W>(&self, f: G) -> mapped::Loader<K, V, W, F, C, G> {
+        mapped::Loader::new(self.clone(), f)
     }
 
-    pub async fn load(&self, key: K) -> V {
-        self.try_load(key).await.unwrap_or_else(|e| panic!("{}", e))
+    /// Preallocates the cache, through [`Cache::reserve`], and the queue of pending keys for
+    /// `capacity` keys, e.g. for a request-scoped loader with a known working set.
+    pub fn with_initial_capacity(self, capacity: usize) -> Self {
+        self.configure_state(|setup| setup.reserve = setup.reserve.max(capacity))
     }
 
-    pub async fn try_load_many(&self, keys: Vec<K>) -> Result<HashMap<K, V>, Error> {
-        let mut state = self.state.lock().await;
-        let mut ret = HashMap::new();
-        let mut rest = Vec::new();
-        for key in keys.into_iter() {
-            if let Some(v) = state.completed.get(&key).cloned() {
-                ret.insert(key, v);
-                continue;
-            }
-            if !state.pending.contains(&key) {
-                state.pending.insert(key.clone());
-                if state.pending.len() >= self.max_batch_size {
-                    let keys = state.pending.drain().collect::<Vec<K>>();
-                    let mut load_fn = self.load_fn.lock().await;
-                    let load_ret = load_fn.load(keys.as_ref()).await;
-                    drop(load_fn);
-                    for (k, v) in load_ret.into_iter() {
-                        state.completed.insert(k, v);
-                    }
-                }
-            }
-            rest.push(key);
+    // Records a setting for the state and applies it right away, unless another handle holds
+    // the state lock, e.g. mid-load, in which case the next lock of the state applies it.
+    fn configure_state(self, f: impl FnOnce(&mut Setup<K, V>)) -> Self {
+        f(&mut self.setup.lock().unwrap());
+        if let Some(mut state) = runtime::try_lock(&self.state) {
+            self.apply_setup(&mut state);
         }
-        drop(state);
-
-        (self.wait_for_work_fn)().await;
-
-        if !rest.is_empty() {
-            let mut state = self.state.lock().await;
-            if !state.pending.is_empty() {
-                let keys = state.pending.drain().collect::<Vec<K>>();
-                let mut load_fn = self.load_fn.lock().await;
-                let load_ret = load_fn.load(keys.as_ref()).await;
-                drop(load_fn);
-                for (k, v) in load_ret.into_iter() {
-                    state.completed.insert(k, v);
-                }
-            }
-
-            for key in rest.into_iter() {
-                let v = state.completed.get(&key).cloned().ok_or(Error::new(
-                    ErrorKind::NotFound,
-                    format!("could not lookup result for given key: {:?}", key),
-                ))?;
+        self
+    }
 
-                ret.insert(key, v);
-            }
+    fn apply_setup(&self, state: &mut State<K, V, C>) {
+        let mut setup = self.setup.lock().unwrap();
+        if setup.reserve > 0 {
+            state.completed.reserve(setup.reserve);
+            state.pending.reserve(setup.reserve);
+            setup.reserve = 0;
         }
+        if let Some(hook) = setup.cache_on_evict.take() {
+            state.completed.set_on_evict(hook);
+        }
+        if let Some(on_evict) = setup.on_evict.take() {
+            state.on_evict = Some(on_evict);
+        }
+    }
 
-        Ok(ret)
+    // Locks the state, applying the settings builder methods couldn't apply yet.
+    async fn lock_state(&self) -> MutexGuard<'_, State<K, V, C>> {
+        let mut state = self.state.lock().await;
+        self.apply_setup(&mut state);
+        state
     }
 
-    pub async fn load_many(&self, keys: Vec<K>) -> HashMap<K, V> {
-        self.try_load_many(keys)
-            .await
-            .unwrap_or_else(|e| panic!("{}", e))
+    /// Treats a batch that doesn't return every requested key as a data-integrity bug, e.g. for
+    /// loaders backed by foreign keys. Loads of the missing keys fail with an [`IntegrityError`]
+    /// listing all keys the batch left unresolved, instead of a `KeyNotFound` for each.
+    pub fn with_require_all_keys(mut self, require_all_keys: bool) -> Self {
+        self.require_all_keys = require_all_keys;
+        self
     }
 
-    pub async fn prime(&self, key: K, val: V) {
-        let mut state = self.state.lock().await;
-        state.completed.insert(key, val);
+    /// Dispatches at most `rps` batches per second, shared by all handles of this loader, e.g.
+    /// for a rate-limited third-party API. Keys keep queueing while a batch waits for its turn,
+    /// so throttled batches also tend to be fuller. Unlike limiting concurrency, this bounds how
+    /// often the batch function is called, not how many calls overlap. An `rps` of `0` is
+    /// treated as `1`, so the loader still makes progress.
+    pub fn with_rate_limit(mut self, rps: u32) -> Self {
+        self.rate_limit = Some(Arc::new(RateLimiter::per_second(rps)));
+        self
     }
 
-    pub async fn prime_many(&self, values: impl IntoIterator<Item = (K, V)>) {
-        let mut state = self.state.lock().await;
-        for (k, v) in values.into_iter() {
-            state.completed.insert(k, v);
+    /// Runs at most `n` calls of the batch function at once, shared by all handles of this
+    /// loader; further batches wait for a running one to finish. Batches take turns on a shared
+    /// batch function anyway, so this matters with [`Self::with_concurrent_dispatch`], e.g. to
+    /// stay within a database's connection limit. An `n` of 0 is treated as 1.
+    pub fn with_max_concurrent_batches(mut self, n: usize) -> Self {
+        self.batch_permits = Some(Arc::new(Semaphore::new(n)));
+        self
+    }
+
+    /// Development aid: calls `on_warn` once with a message if a run of 64 batches averages
+    /// about one key each, which usually means loads are awaited one by one instead of being
+    /// issued concurrently. Pass e.g. `|msg| eprintln!("{}", msg)` or a logging macro. Only
+    /// active in debug builds; release builds ignore it.
+    pub fn with_unbatched_warning(
+        mut self,
+        on_warn: impl Fn(&str) + Send + Sync + 'static,
+    ) -> Self {
+        if cfg!(debug_assertions) {
+            self.unbatched = Some(Arc::new(UnbatchedMonitor::new(on_warn)));
         }
+        self
     }
 
-    pub async fn clear(&self, key: K) {
-        let mut state = self.state.lock().await;
-        state.completed.remove(&key);
+    /// Batch and cache counters since the loader was created, shared by all of its handles and
+    /// by the loaders [`Self::split`] from it.
+    pub fn stats(&self) -> LoaderStats {
+        self.counters.snapshot()
+    }
+
+    /// Records recent batches and cache lookups for [`Loader::recent_stats`]. Off by default, as
+    /// every load then also updates the shared counters.
+    pub fn with_recent_stats(mut self) -> Self {
+        self.recent_stats = Some(Arc::new(RecentStats::new()));
+        self
+    }
+
+    /// Average batch size, p99 batch latency and cache hit rate over the last `window`, e.g. for
+    /// a health endpoint. Covers at most the last 4096 batches and the last hour of lookups, and
+    /// is all zeros unless the loader was built [`Loader::with_recent_stats`].
+    pub fn recent_stats(&self, window: Duration) -> WindowedStats {
+        self.recent_stats
+            .as_ref()
+            .map(|stats| stats.window(window))
+            .unwrap_or_default()
+    }
+
+    /// Memoizes loaded values for the loader's lifetime, for immutable reference data such as
+    /// country codes. [`Self::clear`], [`Self::clear_hard`] and [`Self::clear_all`] then do
+    /// nothing, so invalidating mutable data can't drop these entries by accident; use
+    /// [`Self::force_clear_all`] to empty the cache anyway. Priming still replaces values, and
+    /// bounded caches and TTLs still evict.
+    pub fn with_permanent_cache(mut self, permanent: bool) -> Self {
+        self.permanent = permanent;
+        self
+    }
+
+    pub fn with_yield_count(mut self, yield_count: usize) -> Self {
+        self.wait_for_work_fn = Arc::new(yield_fn(yield_count));
+        self.trigger_keys = None;
+        self
+    }
+
+    /// Replaces the yielding for work behavior with an arbitrary future. Rather than yielding
+    /// the runtime repeatedly this will generate and `.await` a future of your choice.
+    /// ***This is incompatible with*** [`Self::with_yield_count()`].
+    pub fn with_custom_wait_for_work(mut self, wait_for_work_fn: impl WaitForWorkFn) -> Self {
+        self.wait_for_work_fn = Arc::new(wait_for_work_fn);
+        self.trigger_keys = None;
+        self
+    }
+
+    /// Waits `window` of wall-clock time for more keys before dispatching a batch, instead of
+    /// yielding a number of times, so batching doesn't depend on how busy the scheduler is.
+    /// ***This is incompatible with*** [`Self::with_yield_count()`]: whichever is set last wins,
+    /// unless the loader is built through a [`LoaderBuilder`].
+    pub fn with_batch_window(self, window: Duration) -> Self {
+        self.with_custom_wait_for_work(sleep_fn(window))
+    }
+
+    /// Dispatches queued keys only once `notify` fires, or once a batch fills up, instead of
+    /// after a batching window, e.g. to tie dispatch to the end of a resolution pass. Replaces
+    /// the wait for work like [`Self::with_custom_wait_for_work()`].
+    pub fn with_dispatch_notifier(self, notify: Arc<Notify>) -> Self {
+        self.with_custom_wait_for_work(notified(notify))
+    }
+
+    /// Dispatches queued keys as soon as `min_keys` of them are pending, or `max_delay` after
+    /// the first caller started waiting, whichever comes first. A full batch still dispatches
+    /// right away. Replaces the wait for work like [`Self::with_custom_wait_for_work()`].
+    pub fn with_batch_trigger(self, min_keys: usize, max_delay: Duration) -> Self {
+        let mut loader = self.with_batch_window(max_delay);
+        loader.trigger_keys = Some(min_keys.max(1));
+        loader
+    }
+
+    /// Uses a separate wait strategy for `load_many` and friends, which already bring a batch's
+    /// worth of keys, while single loads keep the regular window. Pass `wait::yield_fn(0)` to
+    /// dispatch bulk calls right away, at the cost of not coalescing concurrent bulk calls.
+    pub fn with_load_many_wait_for_work(mut self, wait_for_work_fn: impl WaitForWorkFn) -> Self {
+        self.load_many_wait_fn = Some(Arc::new(wait_for_work_fn));
+        self
+    }
+
+    /// Stops calling the [`BatchFn`]: every dispatched batch is recorded instead and its keys
+    /// resolve to `default_fn(key)`. Use [`Self::dry_run_batches()`] to inspect how loads were
+    /// batched, e.g. to confirm that resolvers are not issuing one batch per key.
+    pub fn with_dry_run(mut self, default_fn: impl Fn(&K) -> V + Send + Sync + 'static) -> Self {
+        self.dry_run = Some(Arc::new(DryRun {
+            default_fn: Box::new(default_fn),
+            batches: std::sync::Mutex::new(Vec::new()),
+        }));
+        self
+    }
+
+    /// The batches recorded in dry-run mode, in dispatch order. Empty unless
+    /// [`Self::with_dry_run()`] was used.
+    pub fn dry_run_batches(&self) -> Vec<Vec<K>> {
+        match &self.dry_run {
+            Some(dry_run) => dry_run.batches.lock().unwrap().clone(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn max_batch_size(&self) -> usize {
+        self.max_batch_size
+    }
+
+    /// Returns `true` if both handles were cloned from the same loader and so share its state.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+
+    /// The number of pending keys that triggers an early dispatch right now. This is
+    /// [`Self::max_batch_size()`] unless adaptive batch sizing is enabled.
+    pub fn current_batch_size(&self) -> usize {
+        match &self.adaptive {
+            Some(adaptive) => adaptive.current(),
+            None => self.max_batch_size,
+        }
+    }
+
+    // Whether enough keys are queued to dispatch them without waiting for more.
+    fn batch_full(&self, state: &State<K, V, C>) -> bool {
+        let queued = state.pending.len();
+        let batch_size = self.current_batch_size();
+        if batch_size == 0 {
+            return true;
+        }
+        if queued < batch_size && self.max_pending.is_some_and(|max| queued >= max) {
+            return true;
+        }
+        if queued < batch_size {
+            return self.trigger_keys.is_some_and(|min_keys| queued >= min_keys);
+        }
+        if let Some(on_batch_full) = &self.on_batch_full {
+            on_batch_full(queued);
+        }
+        true
+    }
+
+    /// Resolves when the next batch is dispatched, with the keys it carries. Every waiter that
+    /// started waiting before the dispatch sees the same batch.
+    #[cfg(feature = "testing")]
+    pub async fn next_batch(&self) -> BatchEvent<K> {
+        let event = self.lock_state().await.next_batch.clone();
+        event.wait().await
+    }
+
+    // Why a completed `batch` has no value for `key`, if not `KeyNotFound`.
+    fn batch_error(&self, batch: &Batch<K, V>, key: &K) -> Option<LoadError<K>> {
+        if batch.panicked.lock().unwrap().contains(key) {
+            return Some(LoadError::BatchPanicked(key.clone()));
+        }
+        if !self.require_all_keys {
+            return None;
+        }
+        let missing = batch.missing.lock().unwrap();
+        if !missing.contains(key) {
+            return None;
+        }
+        Some(LoadError::Integrity(IntegrityError {
+            missing: missing.clone(),
+        }))
+    }
+
+    fn expires_at(&self, val: &V) -> Option<Instant> {
+        let ttl = (self.value_ttl.as_ref()?)(val)?;
+        Instant::now().checked_add(ttl)
+    }
+
+    // Hands the pending keys to the batch function. The state lock is released while the batch
+    // is in flight, so cache hits, priming and other batches aren't held up behind a slow
+    // `load_fn`. The results are kept on the batch as well as cached, so every caller waiting on
+    // it gets its value even if a bounded cache evicts it right away.
+    async fn dispatch<'a>(
+        &'a self,
+        mut state: MutexGuard<'a, State<K, V, C>>,
+    ) -> MutexGuard<'a, State<K, V, C>> {
+        if let Some(rate_limit) = &self.rate_limit {
+            while !state.pending.is_empty() {
+                match rate_limit.try_acquire() {
+                    Ok(()) => break,
+                    Err(wait) => {
+                        drop(state);
+                        runtime::sleep(wait).await;
+                        state = self.lock_state().await;
+                    }
+                }
+            }
+        }
+        if state.pending.is_empty() {
+            return state;
+        }
+        // keys queued beyond the cap, e.g. while waiting for the rate limit, stay pending; a
+        // cap of 0 sends one key at a time
+        let limit = self.max_batch_size.max(1);
+        let mut keys = if state.pending.len() <= limit {
+            state.pending.drain().collect::<Vec<K>>()
+        } else {
+            let keys = state
+                .pending
+                .iter()
+                .take(limit)
+                .cloned()
+                .collect::<Vec<K>>();
+            for k in keys.iter() {
+                state.pending.remove(k);
+            }
+            keys
+        };
+        if let Some(sort_keys) = self.sort_keys {
+            sort_keys(&mut keys);
+        }
+        let batch = std::mem::replace(&mut state.queued, Arc::new(Batch::new()));
+        self.load_batch(state, keys, batch).await
+    }
+
+    // Dispatches batches while `max_pending` keys are queued, so queueing `key` can't grow the
+    // queue past the bound. Keys already queued or in flight don't need room.
+    async fn make_room<'a>(
+        &'a self,
+        mut state: MutexGuard<'a, State<K, V, C>>,
+        key: &K,
+    ) -> MutexGuard<'a, State<K, V, C>> {
+        if let Some(max_pending) = self.max_pending {
+            while state.pending.len() >= max_pending
+                && !state.pending.contains(key)
+                && !state.loading.contains_key(key)
+            {
+                state = self.dispatch(state).await;
+            }
+        }
+        state
+    }
+
+    async fn batch_permit(&self) -> Option<Permit<'_>> {
+        match &self.batch_permits {
+            Some(batch_permits) => Some(batch_permits.acquire().await),
+            None => None,
+        }
+    }
+
+    async fn call_load_fn(&self, keys: &[K]) -> HashMap<K, V> {
+        match self.clone_load_fn {
+            Some(clone_load_fn) => {
+                let mut load_fn = clone_load_fn(&*self.load_fn.lock().await);
+                instrument::batch(keys.len(), load_fn.load(keys)).await
+            }
+            None => {
+                let mut load_fn = self.load_fn.lock().await;
+                instrument::batch(keys.len(), load_fn.load(keys)).await
+            }
+        }
+    }
+
+    // Loads `keys` as `batch`, which callers may already be waiting on, and caches the results.
+    async fn load_batch<'a>(
+        &'a self,
+        mut state: MutexGuard<'a, State<K, V, C>>,
+        keys: Vec<K>,
+        batch: Arc<Batch<K, V>>,
+    ) -> MutexGuard<'a, State<K, V, C>> {
+        #[cfg(feature = "testing")]
+        std::mem::replace(&mut state.next_batch, Arc::new(Event::new()))
+            .set(BatchEvent { keys: keys.clone() });
+        for k in keys.iter() {
+            state.cancellable.remove(k);
+            state.loading.insert(k.clone(), batch.clone());
+        }
+        drop(state);
+
+        let _done = SetOnDrop(&batch);
+        let load_ret = match &self.dry_run {
+            Some(dry_run) => {
+                dry_run.batches.lock().unwrap().push(keys.clone());
+                keys.iter()
+                    .map(|k| (k.clone(), (dry_run.default_fn)(k)))
+                    .collect()
+            }
+            None => {
+                let mut permit = self.batch_permit().await;
+                let started = Instant::now();
+                let mut load_ret = match unless_panicked(self.call_load_fn(&keys)).await {
+                    Some(load_ret) => load_ret,
+                    None => {
+                        *batch.panicked.lock().unwrap() = keys.clone();
+                        HashMap::new()
+                    }
+                };
+                if let Some(adaptive) = &self.adaptive {
+                    adaptive.observe(keys.len(), started.elapsed());
+                }
+                self.counters.record_batch(keys.len());
+                if let Some(recent_stats) = &self.recent_stats {
+                    recent_stats.record_batch(keys.len(), started.elapsed());
+                }
+                if let Some(retry) = &self.retry {
+                    for _ in 0..retry.max_retries {
+                        let failed = keys
+                            .iter()
+                            .filter(|k| load_ret.get(k).is_some_and(|v| (retry.retryable)(v)))
+                            .cloned()
+                            .collect::<Vec<_>>();
+                        if failed.is_empty() {
+                            break;
+                        }
+                        // other batches may run while this one backs off
+                        drop(permit.take());
+                        runtime::sleep(retry.backoff).await;
+                        permit = self.batch_permit().await;
+                        // a panicking retry leaves the keys with the errors they had
+                        match unless_panicked(self.call_load_fn(&failed)).await {
+                            Some(retried) => load_ret.extend(retried),
+                            None => break,
+                        }
+                    }
+                }
+                drop(permit);
+                load_ret
+            }
+        };
+
+        if let Some(unbatched) = &self.unbatched {
+            unbatched.observe(keys.len());
+        }
+
+        let mut state = self.lock_state().await;
+        for k in keys.iter() {
+            if state.loading.get(k).is_some_and(|b| Arc::ptr_eq(b, &batch)) {
+                state.loading.remove(k);
+            }
+        }
+        let discarded = std::mem::take(&mut *batch.discarded.lock().unwrap());
+        if self.require_all_keys {
+            *batch.missing.lock().unwrap() = keys
+                .iter()
+                .filter(|k| !load_ret.contains_key(k) && !discarded.contains(k))
+                .cloned()
+                .collect();
+        }
+        let mut results = batch.results.lock().unwrap();
+        for (k, v) in load_ret.into_iter() {
+            if !discarded.contains(&k) {
+                results.insert(k.clone(), v.clone());
+                if self.cache_if.as_ref().is_none_or(|cache_if| cache_if(&v)) {
+                    let expires_at = self.expires_at(&v);
+                    state.insert(k, v, expires_at);
+                }
+            }
+        }
+        drop(results);
+        if !state.on_resolved.is_empty() {
+            for k in keys.iter() {
+                if state.on_resolved.contains_key(k) {
+                    let result = match batch.result(k) {
+                        Some(v) => Ok(v),
+                        None => Err(self
+                            .batch_error(&batch, k)
+                            .unwrap_or_else(|| LoadError::KeyNotFound(k.clone()))),
+                    };
+                    state.resolved(k, &result);
+                }
+            }
+        }
+        state
+    }
+
+    // Runs a wait for work, cut short once `batch` has completed, e.g. because `flush` or a
+    // full batch dispatched it early.
+    async fn wait_for_work(&self, wait_for_work_fn: &Arc<dyn WaitForWorkFn>, batch: &Batch<K, V>) {
+        let mut wait = wait_for_work_fn();
+        let mut done = std::pin::pin!(batch.done.wait());
+        instrument::wait(std::future::poll_fn(|cx| {
+            if done.as_mut().poll(cx).is_ready() || wait.as_mut().poll(cx).is_ready() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }))
+        .await
+    }
+
+    fn record_lookups(&self, hits: usize, misses: usize) {
+        self.counters.record_lookups(hits, misses);
+        if let Some(recent_stats) = &self.recent_stats {
+            recent_stats.record_lookups(hits, misses);
+        }
+    }
+
+    // Waits until `key`, queued in `batch`, is neither queued nor in flight, dispatching the
+    // queued keys if no one else has, and returns its value if the batch function resolved it.
+    // Otherwise the error is `KeyNotFound`, or an integrity error if the key's batch failed that
+    // check. A `fresh` caller ignores values cached before its batch completed.
+    async fn resolve<'a>(
+        &'a self,
+        mut state: MutexGuard<'a, State<K, V, C>>,
+        key: &K,
+        mut batch: Arc<Batch<K, V>>,
+        fresh: bool,
+    ) -> (MutexGuard<'a, State<K, V, C>>, Result<V, LoadError<K>>) {
+        loop {
+            if batch.done.is_set() {
+                if let Some(v) = batch.result(key) {
+                    return (state, Ok(v));
+                }
+                if let Some(e) = self.batch_error(&batch, key) {
+                    return (state, Err(e));
+                }
+            }
+            if !fresh || batch.done.is_set() {
+                if let Some(v) = state.get(key).cloned() {
+                    return (state, Ok(v));
+                }
+            }
+            if let Some(loading) = state.loading.get(key).cloned() {
+                if loading.done.is_set() {
+                    // the batch's dispatcher was dropped mid-flight; queue the key again
+                    state.loading.remove(key);
+                    batch = state.enqueue(key);
+                    continue;
+                }
+                drop(state);
+                loading.done.wait().await;
+                state = self.lock_state().await;
+                batch = loading;
+                continue;
+            }
+            if !state.pending.contains(key) {
+                return (state, Err(LoadError::KeyNotFound(key.clone())));
+            }
+            batch = state.queued.clone();
+            state = self.dispatch(state).await;
+        }
+    }
+
+    /// Like [`Self::try_load`], but a miss is loaded right away in a batch of its own, skipping
+    /// the wait for work, for one-off lookups that gain nothing from batching. A key that is
+    /// already in flight is still shared with that batch, and one waiting to be dispatched is
+    /// taken from the queue, handing its callers this load's result.
+    pub async fn load_immediate(&self, key: K) -> Result<V, LoadError<K>> {
+        let mut state = self.lock_state().await;
+        if let Some(v) = state.get(&key) {
+            self.record_lookups(1, 0);
+            return Ok((*v).clone());
+        }
+        self.record_lookups(0, 1);
+
+        let batch = loop {
+            if let Some(batch) = state.loading.get(&key) {
+                break batch.clone();
+            }
+            // only a batch of its own waits for the rate limit, and the key may load meanwhile
+            if let Some(Err(wait)) = self.rate_limit.as_ref().map(|r| r.try_acquire()) {
+                drop(state);
+                runtime::sleep(wait).await;
+                state = self.lock_state().await;
+                if let Some(v) = state.get(&key) {
+                    return Ok((*v).clone());
+                }
+                continue;
+            }
+            state.pending.remove(&key);
+            let batch = Arc::new(Batch::new());
+            state = self
+                .load_batch(state, vec![key.clone()], batch.clone())
+                .await;
+            break batch;
+        };
+        let (_, v) = self.resolve(state, &key, batch, false).await;
+        v
+    }
+
+    /// Loads the given keys that aren't cached or in flight right away, in batches of at most
+    /// [`Self::max_batch_size()`] and without waiting for work, so later loads of them hit the
+    /// cache, e.g. for the IDs a request is known to need. Keys waiting to be dispatched are
+    /// taken from the queue. Keys the batch function doesn't return are not reported.
+    pub async fn prewarm(&self, keys: Vec<K>) {
+        let keys = {
+            let state = self.lock_state().await;
+            let mut seen = HashSet::new();
+            keys.into_iter()
+                .filter(|k| !state.contains_key(k) && !state.loading.contains_key(k))
+                .filter(|k| seen.insert(k.clone()))
+                .collect::<Vec<_>>()
+        };
+        for chunk in keys.chunks(self.max_batch_size.max(1)) {
+            if let Some(rate_limit) = &self.rate_limit {
+                while let Err(wait) = rate_limit.try_acquire() {
+                    runtime::sleep(wait).await;
+                }
+            }
+            let mut state = self.lock_state().await;
+            // keys loaded by someone else since
+            let chunk = chunk
+                .iter()
+                .filter(|k| !state.contains_key(k) && !state.loading.contains_key(k))
+                .cloned()
+                .collect::<Vec<_>>();
+            if chunk.is_empty() {
+                continue;
+            }
+            for k in chunk.iter() {
+                state.pending.remove(k);
+            }
+            drop(self.load_batch(state, chunk, Arc::new(Batch::new())).await);
+        }
+    }
+
+    /// Returns the cached value for `key`, or `None` right away if it isn't cached. Never queues
+    /// or dispatches the key.
+    pub async fn peek(&self, key: &K) -> Option<V> {
+        self.lock_state().await.get(key).cloned()
+    }
+
+    /// Whether `key` has a cached value, like [`Loader::peek`] but without cloning it.
+    pub async fn is_cached(&self, key: &K) -> bool {
+        self.lock_state().await.contains_key(key)
+    }
+
+    /// Returns the cached value for `key` without building a future, for warm reads from sync
+    /// code. Never queues or dispatches the key, so `None` means the caller should fall back to
+    /// an async load. The state is only `try_lock`ed: while another task holds the lock, e.g.
+    /// while queueing keys or caching a batch's results, this returns `None` even for a cached
+    /// key rather than blocking.
+    #[cfg(feature = "blocking")]
+    pub fn get_cached(&self, key: &K) -> Option<V> {
+        let mut state = runtime::try_lock(&self.state)?;
+        self.apply_setup(&mut state);
+        state.get(key).cloned()
+    }
+
+    pub async fn try_load(&self, key: K) -> Result<V, LoadError<K>> {
+        if let (Some(reader), None) = (&self.reader, &self.value_ttl) {
+            if let Some(v) = reader(&key) {
+                self.record_lookups(1, 0);
+                return Ok(v);
+            }
+        }
+        let mut state = self.lock_state().await;
+        if let Some(v) = state.get(&key) {
+            self.record_lookups(1, 0);
+            return Ok((*v).clone());
+        }
+        self.record_lookups(0, 1);
+
+        state = self.make_room(state, &key).await;
+        let batch = state.enqueue(&key);
+        if self.batch_full(&state) {
+            let state = self.dispatch(state).await;
+            let (_, v) = self.resolve(state, &key, batch, false).await;
+            return v;
+        }
+        drop(state);
+
+        self.wait_for_work(&self.wait_for_work_fn, &batch).await;
+
+        let state = self.lock_state().await;
+        let (_, v) = self.resolve(state, &key, batch, false).await;
+        v
+    }
+
+    pub async fn load(&self, key: K) -> V {
+        self.try_load(key).await.unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Returns the loaded value, or `default()` if the batch function didn't resolve the key, e.g.
+    /// for an optional relation with no row. The fallback isn't cached, so the key is loaded
+    /// again next time. Like [`Self::load`], panics on any other error.
+    pub async fn load_or_else<G: FnOnce() -> V>(&self, key: K, default: G) -> V {
+        match self.try_load(key).await {
+            Ok(v) => v,
+            Err(LoadError::KeyNotFound(_)) => default(),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Like [`Self::try_load`], but fails with [`LoadError::Timeout`] if the key doesn't resolve
+    /// within `timeout`, e.g. because the batch function is stuck. Timing out drops this call's
+    /// load: if it was dispatching the batch, the batch is abandoned and other callers of its
+    /// keys queue them again. Use [`Self::load_or`] to leave the load to its other callers.
+    pub async fn try_load_timeout(&self, key: K, timeout: Duration) -> Result<V, LoadError<K>> {
+        match runtime::timeout(timeout, self.try_load(key.clone())).await {
+            Ok(ret) => ret,
+            Err(_) => Err(LoadError::Timeout(key)),
+        }
+    }
+
+    /// Returns the loaded value if it resolves within `timeout`, otherwise `fallback`. The
+    /// fallback is also returned if the batch function doesn't resolve the key.
+    ///
+    /// Timing out doesn't abandon the load: the key stays queued, or its batch in flight, and
+    /// whichever call dispatches it caches the value. The timeout isn't observed while this call
+    /// is dispatching the batch itself. With `runtime-tokio`, call it from within a tokio runtime
+    /// with the time driver enabled, as the timeout runs on tokio's timer.
+    pub async fn load_or(&self, key: K, timeout: Duration, fallback: V) -> V {
+        match self.load_unless(key, runtime::sleep(timeout), false).await {
+            Ok(Some(v)) => v,
+            _ => fallback,
+        }
+    }
+
+    /// Like [`Self::try_load`], but gives up with `Ok(None)` as soon as `cancel` completes, e.g.
+    /// `token.cancelled()` for a client that disconnected. A key queued only by cancelled loads
+    /// is withdrawn before its batch is dispatched; while any other caller still waits on it, it
+    /// stays queued for them. A batch already in flight still runs and caches its results. The
+    /// cancellation isn't observed while this call is dispatching the batch itself.
+    pub async fn try_load_with_cancel(
+        &self,
+        key: K,
+        cancel: impl Future<Output = ()>,
+    ) -> Result<Option<V>, LoadError<K>> {
+        self.load_unless(key, cancel, true).await
+    }
+
+    // Loads `key`, giving up with `Ok(None)` if `cancel` completes while waiting for its batch.
+    // With `withdraw`, a key no one else waits on is unqueued on giving up; otherwise it stays
+    // queued for the next dispatch.
+    async fn load_unless(
+        &self,
+        key: K,
+        cancel: impl Future<Output = ()>,
+        withdraw: bool,
+    ) -> Result<Option<V>, LoadError<K>> {
+        let mut cancel = std::pin::pin!(cancel);
+        let mut state = self.lock_state().await;
+        if let Some(v) = state.get(&key) {
+            self.record_lookups(1, 0);
+            return Ok(Some((*v).clone()));
+        }
+        self.record_lookups(0, 1);
+
+        state = self.make_room(state, &key).await;
+        let mut batch = if withdraw {
+            state.enqueue_cancellable(&key)
+        } else {
+            state.enqueue(&key)
+        };
+        if !self.batch_full(&state) {
+            drop(state);
+            let wait = self.wait_for_work(&self.wait_for_work_fn, &batch);
+            let waited = unless_cancelled(wait, cancel.as_mut()).await;
+            state = self.lock_state().await;
+            if waited.is_none() {
+                if withdraw {
+                    state.cancel(&key);
+                }
+                return Ok(None);
+            }
+            // past its wait, this caller dispatches the key if no one else has
+            state.cancellable.remove(&key);
+            if let Some(loading) = state.loading.get(&key).cloned() {
+                drop(state);
+                if unless_cancelled(loading.done.wait(), cancel.as_mut())
+                    .await
+                    .is_none()
+                {
+                    return Ok(None);
+                }
+                state = self.lock_state().await;
+                batch = loading;
+            }
+        } else {
+            state = self.dispatch(state).await;
+        }
+        let (_, v) = self.resolve(state, &key, batch, false).await;
+        v.map(Some)
+    }
+
+    pub async fn try_load_many(&self, keys: Vec<K>) -> Result<HashMap<K, V>, LoadError<K>> {
+        let mut ret = HashMap::new();
+        self.try_load_many_into(keys, &mut ret).await?;
+        Ok(ret)
+    }
+
+    /// Like [`Self::try_load_many`], but inserts the results into `out` so a map can be reused
+    /// across calls. `out` is extended, not cleared; on error it may hold some of the results.
+    pub async fn try_load_many_into(
+        &self,
+        keys: Vec<K>,
+        out: &mut HashMap<K, V>,
+    ) -> Result<(), LoadError<K>> {
+        self.load_many_into(keys, out, false).await
+    }
+
+    /// Like [`Self::try_load_many`], but returns one value per key in `keys`, in the same order
+    /// and including duplicates, e.g. to render a list field positionally.
+    pub async fn try_load_many_ordered(&self, keys: Vec<K>) -> Result<Vec<V>, LoadError<K>> {
+        let mut ret = HashMap::new();
+        self.load_many_into(keys.clone(), &mut ret, false).await?;
+        Ok(keys.iter().map(|k| ret[k].clone()).collect())
+    }
+
+    /// Like [`Self::try_load_many`], but ignores cached values: every key is loaded by the batch
+    /// function and the cache updated with the fresh values, e.g. to take a consistent snapshot
+    /// of a set of entities. Loads that are queued or in flight concurrently, fresh or not, are
+    /// still coalesced into the same batches.
+    pub async fn try_load_many_fresh(&self, keys: Vec<K>) -> Result<HashMap<K, V>, LoadError<K>> {
+        let mut ret = HashMap::new();
+        self.load_many_into(keys, &mut ret, true).await?;
+        Ok(ret)
+    }
+
+    /// Like [`Self::try_load_many`], but never stops at an error: every key maps to its own
+    /// outcome, like `Promise.allSettled`. Keys beyond [`Self::with_max_keys_per_call`] are all
+    /// rejected with the same `TooManyKeys` error.
+    pub async fn load_many_settled(&self, keys: Vec<K>) -> HashMap<K, Result<V, LoadError<K>>> {
+        if let Err(e) = self.check_keys_per_call(&keys) {
+            return keys.into_iter().map(|k| (k, Err(e.clone()))).collect();
+        }
+        let mut ret = HashMap::new();
+        let _ = self
+            .load_each(keys, false, |k, v| {
+                ret.insert(k, v);
+                Ok(())
+            })
+            .await;
+        ret
+    }
+
+    fn check_keys_per_call(&self, keys: &[K]) -> Result<(), LoadError<K>> {
+        match self.max_keys_per_call {
+            Some(max_keys) if keys.len() > max_keys => Err(LoadError::TooManyKeys {
+                requested: keys.len(),
+                max: max_keys,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    async fn load_many_into(
+        &self,
+        keys: Vec<K>,
+        out: &mut HashMap<K, V>,
+        fresh: bool,
+    ) -> Result<(), LoadError<K>> {
+        self.check_keys_per_call(&keys)?;
+        self.load_each(keys, fresh, |k, v| {
+            out.insert(k, v?);
+            Ok(())
+        })
+        .await
+    }
+
+    // Loads `keys` in as few batches as possible, handing each outcome to `settle`. Stops at the
+    // first error `settle` returns.
+    async fn load_each(
+        &self,
+        keys: Vec<K>,
+        fresh: bool,
+        mut settle: impl FnMut(K, Result<V, LoadError<K>>) -> Result<(), LoadError<K>>,
+    ) -> Result<(), LoadError<K>> {
+        let mut state = self.lock_state().await;
+        let mut rest = Vec::new();
+        // keys already in `rest`, so repeats of a miss are looked up once
+        let mut queued = HashSet::new();
+        let mut hits = 0;
+        for key in keys.into_iter() {
+            if !fresh {
+                if let Some(v) = state.get(&key).cloned() {
+                    settle(key, Ok(v))?;
+                    hits += 1;
+                    continue;
+                }
+            }
+            if !queued.insert(key.clone()) {
+                continue;
+            }
+            state = self.make_room(state, &key).await;
+            let batch = state.enqueue(&key);
+            if self.batch_full(&state) {
+                state = self.dispatch(state).await;
+            }
+            rest.push((key, batch));
+        }
+        drop(state);
+        self.record_lookups(hits, rest.len());
+
+        if !rest.is_empty() {
+            let wait_for_work_fn = self
+                .load_many_wait_fn
+                .as_ref()
+                .unwrap_or(&self.wait_for_work_fn);
+            let (_, last_batch) = rest.last().unwrap();
+            self.wait_for_work(wait_for_work_fn, last_batch).await;
+
+            let mut state = self.lock_state().await;
+            for (key, batch) in rest.into_iter() {
+                let (next, v) = self.resolve(state, &key, batch, fresh).await;
+                state = next;
+                settle(key, v)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn load_many(&self, keys: Vec<K>) -> HashMap<K, V> {
+        self.try_load_many(keys)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Calls `f` once with the result of the next batch that carries `key`, or right away if
+    /// the value is cached. Priming the key also fires it. Registering doesn't queue the key,
+    /// so pair it with a load if nothing else requests the key. `f` runs while the loader's
+    /// state is locked and should only hand the result off, e.g. over a channel.
+    pub async fn on_resolved(
+        &self,
+        key: K,
+        f: impl FnOnce(&Result<V, LoadError<K>>) + Send + 'static,
+    ) {
+        let mut state = self.lock_state().await;
+        match state.get(&key).cloned() {
+            Some(v) => {
+                drop(state);
+                f(&Ok(v));
+            }
+            None => state.on_resolved.entry(key).or_default().push(Box::new(f)),
+        }
+    }
+
+    /// Caches `val` for `key`, returning the value it replaces, if any.
+    pub async fn prime(&self, key: K, val: V) -> Option<V> {
+        let mut state = self.lock_state().await;
+        let previous = state.get(&key).cloned();
+        let expires_at = self.expires_at(&val);
+        state.prime(key, val, expires_at);
+        previous
+    }
+
+    /// Caches `val` for `key` unless the key is cached or a batch is already loading it, so
+    /// speculative warming never replaces a value a load resolved. Returns whether it inserted.
+    pub async fn prime_if_absent(&self, key: K, val: V) -> bool {
+        let mut state = self.lock_state().await;
+        if state.loading.contains_key(&key) || state.get(&key).is_some() {
+            return false;
+        }
+        let expires_at = self.expires_at(&val);
+        state.prime(key, val, expires_at);
+        true
+    }
+
+    /// Caches every value, like [`Self::prime`], and reports which keys were new, which
+    /// replaced a different value and which were already cached with an equal one, e.g. to
+    /// check a cache-warming step against live state. Every entry is primed, unchanged ones
+    /// included.
+    pub async fn prime_many(&self, values: impl IntoIterator<Item = (K, V)>) -> PrimeSummary<K>
+    where
+        V: PartialEq,
+    {
+        let mut state = self.lock_state().await;
+        let mut summary = PrimeSummary {
+            inserted: HashSet::new(),
+            overwritten: HashSet::new(),
+            unchanged: HashSet::new(),
+        };
+        for (k, v) in values.into_iter() {
+            let seen = summary.inserted.contains(&k)
+                || summary.overwritten.contains(&k)
+                || summary.unchanged.contains(&k);
+            if !seen {
+                match state.get(&k) {
+                    Some(existing) if *existing == v => summary.unchanged.insert(k.clone()),
+                    Some(_) => summary.overwritten.insert(k.clone()),
+                    None => summary.inserted.insert(k.clone()),
+                };
+            }
+            let expires_at = self.expires_at(&v);
+            state.prime(k, v, expires_at);
+        }
+        summary
+    }
+
+    /// Writes the value through [`BatchFn::store_many`] and, once that succeeds, primes the cache
+    /// with it so later loads are served without another round trip.
+    pub async fn store(&self, key: K, val: V) -> Result<(), Error> {
+        self.store_many(vec![(key, val)]).await
+    }
+
+    /// Bulk form of [`Self::store`]. Nothing is primed if the write fails.
+    pub async fn store_many(&self, values: Vec<(K, V)>) -> Result<(), Error> {
+        let mut load_fn = self.load_fn.lock().await;
+        load_fn.store_many(&values).await?;
+        drop(load_fn);
+        let mut state = self.lock_state().await;
+        for (k, v) in values.into_iter() {
+            let expires_at = self.expires_at(&v);
+            state.prime(k, v, expires_at);
+        }
+        Ok(())
+    }
+
+    pub async fn clear(&self, key: K) {
+        if self.permanent {
+            return;
+        }
+        let mut state = self.lock_state().await;
+        state.remove(&key);
+    }
+
+    /// Dispatches the keys queued so far right away instead of waiting out the batching window,
+    /// e.g. once a request has queued all of its loads. Callers waiting on those keys resolve as
+    /// soon as their batch completes.
+    pub async fn flush(&self) {
+        let mut state = self.lock_state().await;
+        let mut queued = state.pending.len();
+        while queued > 0 && !state.pending.is_empty() {
+            queued = queued.saturating_sub(self.max_batch_size.max(1));
+            state = self.dispatch(state).await;
+        }
+    }
+
+    /// Drops the keys queued so far without dispatching them, e.g. when the request that queued
+    /// them is torn down. Callers waiting on those keys stop waiting and get a `KeyNotFound`
+    /// error, even if they would have liked the batch; keys already in flight are unaffected.
+    pub async fn clear_pending(&self) {
+        let mut state = self.lock_state().await;
+        state.pending.clear();
+        state.cancellable.clear();
+        std::mem::replace(&mut state.queued, Arc::new(Batch::new()))
+            .done
+            .set(());
+    }
+
+    /// Clears every key in `keys` under one lock, e.g. all rows touched by a write.
+    pub async fn clear_many(&self, keys: impl IntoIterator<Item = K>) {
+        if self.permanent {
+            return;
+        }
+        let mut state = self.lock_state().await;
+        for key in keys.into_iter() {
+            state.remove(&key);
+        }
+    }
+
+    /// Clears every cached entry `predicate` matches, e.g. all values of a tenant after a write
+    /// to it, without knowing their keys.
+    pub async fn clear_where<P: FnMut(&K, &V) -> bool>(&self, mut predicate: P) {
+        if self.permanent {
+            return;
+        }
+        let mut state = self.lock_state().await;
+        state.retain(|k, v| !predicate(k, v));
+    }
+
+    /// Like [`Self::clear`], but also withdraws the key if it is still waiting to be dispatched
+    /// or in flight, so no stale result for it is cached. Callers already waiting on the key get
+    /// a `KeyNotFound` error and may retry the load.
+    pub async fn clear_hard(&self, key: K) {
+        if self.permanent {
+            return;
+        }
+        let mut state = self.lock_state().await;
+        state.remove(&key);
+        state.pending.remove(&key);
+        state.cancellable.remove(&key);
+        state.discard_loading(&key);
     }
 
     pub async fn clear_all(&self) {
-        let mut state = self.state.lock().await;
-        state.completed.clear()
+        if !self.permanent {
+            self.force_clear_all().await;
+        }
+    }
+
+    /// Empties the cache even if the loader was built [`Self::with_permanent_cache`].
+    pub async fn force_clear_all(&self) {
+        let mut state = self.lock_state().await;
+        state.clear()
+    }
+
+    /// The number of entries the cache holds, see [`Cache::len`].
+    pub async fn cache_size(&self) -> usize {
+        self.lock_state().await.completed.len()
+    }
+
+    /// A snapshot of the keys queued for the next batch, e.g. to see what a hanging load waits
+    /// on. Keys already handed to the batch function are not included; dispatch is unaffected.
+    pub async fn pending_keys(&self) -> Vec<K> {
+        self.lock_state().await.pending.iter().cloned().collect()
+    }
+
+    /// The number of keys [`Self::pending_keys`] would return.
+    pub async fn pending_count(&self) -> usize {
+        self.lock_state().await.pending.len()
+    }
+
+    /// Swaps in a new batch function, keeping the cache and the queued keys, e.g. for hot reloads.
+    /// It waits for a batch that holds the old function to finish, and batches running on clones
+    /// of it under [`Self::with_concurrent_dispatch`] finish with the old function.
+    pub async fn set_load_fn(&self, load_fn: F) {
+        *self.load_fn.lock().await = load_fn;
+    }
+
+    /// Lends the cache to `f` under the loader's lock, for maintenance the loader has no method
+    /// for, such as a bulk retain or custom eviction pass. Loads wait until `f` returns, so keep
+    /// it short. Changes bypass the loader's bookkeeping: entries removed here are not evictions,
+    /// and keys in flight are still cached when their batch completes. Don't replace a cache
+    /// that has a [`Cache::concurrent_reader`], such as `DashMapCache`, with a new one: hits
+    /// would keep reading the old one.
+    pub async fn with_cache_mut<R>(&self, f: impl FnOnce(&mut C) -> R) -> R {
+        let mut state = self.lock_state().await;
+        f(&mut state.completed)
+    }
+}
+
+impl<K, V, F, C> Loader<K, V, F, C>
+where
+    K: Eq + Hash + Clone + Debug + Ord,
+    V: Clone,
+    F: BatchFn<K, V>,
+    C: Cache<Key = K, Val = V>,
+{
+    /// Hands the keys of each batch to the batch function sorted ascending rather than in hash
+    /// order, e.g. so the `IN (...)` clauses built from them are stable for query caching. Keys
+    /// within a batch are always distinct.
+    pub fn with_sorted_batches(mut self) -> Self {
+        self.sort_keys = Some(<[K]>::sort_unstable);
+        self
+    }
+}
+
+impl<K, T, E, F, C> Loader<K, Result<T, E>, F, C>
+where
+    K: Eq + Hash + Clone + Debug,
+    T: Clone + 'static,
+    E: Clone + 'static,
+    F: BatchFn<K, Result<T, E>>,
+    C: Cache<Key = K, Val = Result<T, E>>,
+{
+    /// Asks the batch function again, up to `max_retries` times and `backoff` apart, for the
+    /// keys of a batch that failed with an error `retryable` accepts, e.g. a timeout or a
+    /// dropped connection. Callers wait for the retries and get the last outcome. Retried keys
+    /// are loaded together, so a whole failed batch is retried as one.
+    pub fn with_retry(
+        mut self,
+        max_retries: usize,
+        backoff: Duration,
+        retryable: impl Fn(&E) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.retry = Some(Arc::new(Retry {
+            max_retries,
+            backoff,
+            retryable: Box::new(move |v| matches!(v, Err(e) if retryable(e))),
+        }));
+        self
+    }
+}
+
+impl<K, V, F, C> Loader<K, V, F, C>
+where
+    K: Eq + Hash + Clone + Debug,
+    V: Clone,
+    F: BatchFn<K, V> + Clone,
+    C: Cache<Key = K, Val = V>,
+{
+    /// Runs each batch on its own clone of the batch function instead of taking turns on the
+    /// shared one, so independent batches are in flight at the same time. Suited to stateless
+    /// batch functions and ones whose clones share state, such as a connection pool handle or
+    /// `Arc<impl SharedBatchFn>`.
+    pub fn with_concurrent_dispatch(mut self) -> Self {
+        self.clone_load_fn = Some(F::clone);
+        self
+    }
+}
+
+impl<K, V, F, C> Loader<K, V, F, C>
+where
+    K: Eq + Hash + Clone + Debug + 'static,
+    V: Clone + 'static,
+    F: BatchFn<K, V>,
+    C: Cache<Key = K, Val = V> + Default,
+{
+    /// Creates `n` loaders that share this loader's batch function and settings but each start
+    /// with an empty cache and batch only their own keys, e.g. one per worker of a batch job
+    /// that owns a disjoint key range. The splits still take turns on the batch function unless
+    /// it runs [`Self::with_concurrent_dispatch()`].
+    pub async fn split(&self, n: usize) -> Vec<Self> {
+        let on_evict = self.lock_state().await.on_evict.clone();
+        (0..n)
+            .map(|_| {
+                let mut state = State::with_cache(C::default());
+                if let Some(on_evict) = &on_evict {
+                    let hook = on_evict.clone();
+                    state
+                        .completed
+                        .set_on_evict(Box::new(move |k, v| hook(k, v)));
+                    state.on_evict = Some(on_evict.clone());
+                }
+                Loader {
+                    reader: state.completed.concurrent_reader().map(Arc::new),
+                    state: Arc::new(Mutex::new(state)),
+                    setup: Arc::default(),
+                    ..self.clone()
+                }
+            })
+            .collect()
+    }
+
+    /// A loader with this one's batch function and settings but an empty cache and queue of its
+    /// own, e.g. one per GraphQL request so cached values never leak across requests. Same as
+    /// the single loader [`Self::split`] returns for `n` = 1.
+    pub async fn fork(&self) -> Self {
+        self.split(1).await.remove(0)
+    }
+}
+
+impl<K, V, F, S> Loader<K, V, F, HashMap<K, V, S>>
+where
+    K: Eq + Hash + Clone + Debug,
+    V: Clone,
+    F: BatchFn<K, V>,
+    S: BuildHasher,
+{
+    /// Copies the values cached by `other` into this loader, e.g. to fold a sub-request's loader
+    /// into its parent's. Keys cached by both are resolved by `policy`, and entries keep the
+    /// expiry they had in `other`. Returns the number of entries copied. Merging a loader into
+    /// a handle of itself does nothing. Only `HashMap`-backed loaders can be merged, as the
+    /// [`Cache`] trait can't enumerate its entries.
+    pub async fn merge_from(&self, other: &Self, policy: MergePolicy) -> usize {
+        if self.ptr_eq(other) {
+            return 0;
+        }
+        // lock in address order so concurrent merges in both directions can't deadlock
+        let (mut state, other_state) = if Arc::as_ptr(&self.state) < Arc::as_ptr(&other.state) {
+            let state = self.lock_state().await;
+            (state, other.lock_state().await)
+        } else {
+            let other_state = other.lock_state().await;
+            (self.lock_state().await, other_state)
+        };
+        let now = Instant::now();
+        let mut merged = 0;
+        for (k, v) in other_state.completed.iter() {
+            let expires_at = other_state.expiry.get(k).copied();
+            if expires_at.is_some_and(|at| at <= now) {
+                continue;
+            }
+            if policy == MergePolicy::KeepExisting && state.get(k).is_some() {
+                continue;
+            }
+            state.prime(k.clone(), v.clone(), expires_at);
+            merged += 1;
+        }
+        merged
     }
 }
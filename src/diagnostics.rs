@@ -0,0 +1,44 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+const WINDOW: usize = 64;
+
+/// Watches dispatched batch sizes and warns once if a full window of batches averages close to
+/// one key each, the telltale sign of awaiting every load before issuing the next.
+pub(crate) struct UnbatchedMonitor {
+    batches: AtomicUsize,
+    keys: AtomicUsize,
+    warned: AtomicBool,
+    on_warn: Box<dyn Fn(&str) + Send + Sync>,
+}
+
+impl UnbatchedMonitor {
+    pub(crate) fn new(on_warn: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        UnbatchedMonitor {
+            batches: AtomicUsize::new(0),
+            keys: AtomicUsize::new(0),
+            warned: AtomicBool::new(false),
+            on_warn: Box::new(on_warn),
+        }
+    }
+
+    pub(crate) fn observe(&self, batch_len: usize) {
+        if self.warned.load(Ordering::Relaxed) {
+            return;
+        }
+        let keys = self.keys.fetch_add(batch_len, Ordering::Relaxed) + batch_len;
+        if self.batches.fetch_add(1, Ordering::Relaxed) + 1 < WINDOW {
+            return;
+        }
+        self.batches.store(0, Ordering::Relaxed);
+        self.keys.store(0, Ordering::Relaxed);
+        // an average below 1.1 keys per batch
+        if keys * 10 < WINDOW * 11 && !self.warned.swap(true, Ordering::Relaxed) {
+            (self.on_warn)(&format!(
+                "dataloader: the last {} batches averaged {:.2} keys each; loads are likely \
+                 awaited one at a time instead of concurrently, so nothing is batched",
+                WINDOW,
+                keys as f64 / WINDOW as f64
+            ));
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use crate::cached::IntegrityError;
+use std::fmt::{self, Debug, Display};
+use std::io;
+
+/// Why a [`crate::cached::Loader`] couldn't load a key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError<K> {
+    /// The batch function returned no value for the key, or the key was cleared while queued.
+    KeyNotFound(K),
+    /// A `load_many` call asked for more keys than
+    /// [`crate::cached::Loader::with_max_keys_per_call`] allows.
+    TooManyKeys { requested: usize, max: usize },
+    /// The batch function left keys unresolved while
+    /// [`crate::cached::Loader::with_require_all_keys`] is on.
+    Integrity(IntegrityError<K>),
+    /// The key didn't resolve within the time given to
+    /// [`crate::cached::Loader::try_load_timeout`].
+    Timeout(K),
+    /// The batch function panicked while loading the key's batch. The panic is caught, so the
+    /// loader stays usable and later loads call the batch function again.
+    BatchPanicked(K),
+}
+
+impl<K: Debug> Display for LoadError<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::KeyNotFound(key) => {
+                write!(f, "could not lookup result for given key: {:?}", key)
+            }
+            LoadError::TooManyKeys { requested, max } => {
+                write!(f, "too many keys in one call: {} > {}", requested, max)
+            }
+            LoadError::Integrity(e) => Display::fmt(e, f),
+            LoadError::Timeout(key) => write!(f, "timed out loading key: {:?}", key),
+            LoadError::BatchPanicked(key) => {
+                write!(f, "batch function panicked loading key: {:?}", key)
+            }
+        }
+    }
+}
+
+impl<K: Debug> std::error::Error for LoadError<K> {}
+
+/// For callers that report load errors as [`io::Error`]s, with the kinds the loader used
+/// before it had its own error type.
+impl<K> From<LoadError<K>> for io::Error
+where
+    K: Debug + Send + Sync + 'static,
+{
+    fn from(e: LoadError<K>) -> Self {
+        match e {
+            LoadError::KeyNotFound(_) => io::Error::new(io::ErrorKind::NotFound, e),
+            LoadError::TooManyKeys { .. } => io::Error::new(io::ErrorKind::InvalidInput, e),
+            LoadError::Integrity(e) => io::Error::new(io::ErrorKind::InvalidData, e),
+            LoadError::Timeout(_) => io::Error::new(io::ErrorKind::TimedOut, e),
+            LoadError::BatchPanicked(_) => io::Error::other(e),
+        }
+    }
+}
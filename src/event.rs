@@ -0,0 +1,52 @@
+use std::future::poll_fn;
+use std::sync::Mutex;
+use std::task::{Poll, Waker};
+
+/// A value published once to any number of waiters.
+pub(crate) struct Event<T> {
+    inner: Mutex<Inner<T>>,
+}
+
+struct Inner<T> {
+    value: Option<T>,
+    wakers: Vec<Waker>,
+}
+
+impl<T> Event<T> {
+    pub(crate) fn new() -> Self {
+        Event {
+            inner: Mutex::new(Inner {
+                value: None,
+                wakers: Vec::new(),
+            }),
+        }
+    }
+
+    pub(crate) fn is_set(&self) -> bool {
+        self.inner.lock().unwrap().value.is_some()
+    }
+
+    pub(crate) fn set(&self, value: T) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.value = Some(value);
+        for waker in inner.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+impl<T: Clone> Event<T> {
+    pub(crate) async fn wait(&self) -> T {
+        poll_fn(|cx| {
+            let mut inner = self.inner.lock().unwrap();
+            if let Some(value) = &inner.value {
+                return Poll::Ready(value.clone());
+            }
+            if !inner.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                inner.wakers.push(cx.waker().clone());
+            }
+            Poll::Pending
+        })
+        .await
+    }
+}
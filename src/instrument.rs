@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+use std::future::Future;
+
+// Awaits a call to the batch function. With the `tracing` feature this runs in a
+// `dataloader.batch` span recording the key count, and ends with an event reporting how many
+// keys resolved and how long the call took. With the `metrics` feature the key count is
+// recorded in the `dataloader.batch.size` histogram.
+#[cfg_attr(
+    not(any(feature = "tracing", feature = "metrics")),
+    allow(unused_variables)
+)]
+pub(crate) async fn batch<K, V>(
+    keys: usize,
+    load: impl Future<Output = HashMap<K, V>>,
+) -> HashMap<K, V> {
+    #[cfg(feature = "metrics")]
+    metrics::histogram!("dataloader.batch.size").record(keys as f64);
+    #[cfg(feature = "tracing")]
+    {
+        use tracing::Instrument;
+        let span = tracing::debug_span!("dataloader.batch", keys);
+        let started = std::time::Instant::now();
+        let ret = load.instrument(span.clone()).await;
+        span.in_scope(|| {
+            tracing::debug!(
+                resolved = ret.len(),
+                elapsed_us = started.elapsed().as_micros() as u64,
+                "batch loaded"
+            )
+        });
+        ret
+    }
+    #[cfg(not(feature = "tracing"))]
+    load.await
+}
+
+// Awaits a wait for work. With the `metrics` feature its duration is recorded in the
+// `dataloader.wait.duration` histogram, in seconds.
+pub(crate) async fn wait(wait: impl Future<Output = ()>) {
+    #[cfg(feature = "metrics")]
+    let started = std::time::Instant::now();
+    wait.await;
+    #[cfg(feature = "metrics")]
+    metrics::histogram!("dataloader.wait.duration").record(started.elapsed().as_secs_f64());
+}
@@ -0,0 +1,170 @@
+//! A cached loader whose bookkeeping runs on small caller-supplied cache keys.
+//!
+//! [`Loader::load_with_cache_key`] takes a cheap `CK` that identifies the full key `K`: caching
+//! and batching only ever hash `CK`, while the [`BatchFn`] still receives the full keys.
+use crate::cached::{self, Cache};
+use crate::{BatchFn, LoadError, WaitForWorkFn};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+
+// The full key of every cache key that has a load in progress, with the number of such loads.
+type FullKeys<CK, K> = Arc<Mutex<HashMap<CK, (K, usize)>>>;
+
+/// The [`BatchFn`] used by [`Loader`], translating cache keys to full keys and back.
+pub struct FullKeyBatchFn<CK, K, F> {
+    load_fn: F,
+    full_keys: FullKeys<CK, K>,
+}
+
+impl<CK, K, V, F> BatchFn<CK, V> for FullKeyBatchFn<CK, K, F>
+where
+    CK: Eq + Hash + Clone,
+    K: Eq + Hash + Clone,
+    F: BatchFn<K, V>,
+{
+    async fn load(&mut self, keys: &[CK]) -> HashMap<CK, V> {
+        let requested = {
+            let full_keys = self.full_keys.lock().unwrap();
+            keys.iter()
+                .filter_map(|ck| full_keys.get(ck).map(|(k, _)| (ck.clone(), k.clone())))
+                .collect::<Vec<_>>()
+        };
+        if requested.is_empty() {
+            return HashMap::new();
+        }
+        let full_keys = requested.iter().map(|(_, k)| k.clone()).collect::<Vec<_>>();
+        let mut ret = self.load_fn.load(&full_keys).await;
+        requested
+            .into_iter()
+            .filter_map(|(ck, k)| ret.remove(&k).map(|v| (ck, v)))
+            .collect()
+    }
+}
+
+// Releases a caller's claim on a full key when its load ends, however it ends.
+struct Claim<'a, CK: Eq + Hash, K> {
+    full_keys: &'a Mutex<HashMap<CK, (K, usize)>>,
+    cache_key: &'a CK,
+}
+
+impl<CK: Eq + Hash, K> Drop for Claim<'_, CK, K> {
+    fn drop(&mut self) {
+        let mut full_keys = self.full_keys.lock().unwrap();
+        if let Some((_, claims)) = full_keys.get_mut(self.cache_key) {
+            *claims -= 1;
+            if *claims == 0 {
+                full_keys.remove(self.cache_key);
+            }
+        }
+    }
+}
+
+pub struct Loader<CK, K, V, F, C = HashMap<CK, V>>
+where
+    CK: Eq + Hash + Clone,
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: BatchFn<K, V>,
+    C: Cache<Key = CK, Val = V>,
+{
+    inner: cached::Loader<CK, V, FullKeyBatchFn<CK, K, F>, C>,
+    full_keys: FullKeys<CK, K>,
+}
+
+impl<CK, K, V, F, C> Clone for Loader<CK, K, V, F, C>
+where
+    CK: Eq + Hash + Clone,
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: BatchFn<K, V>,
+    C: Cache<Key = CK, Val = V>,
+{
+    fn clone(&self) -> Self {
+        Loader {
+            inner: self.inner.clone(),
+            full_keys: self.full_keys.clone(),
+        }
+    }
+}
+
+#[allow(clippy::implicit_hasher)]
+impl<CK, K, V, F> Loader<CK, K, V, F, HashMap<CK, V>>
+where
+    CK: Eq + Hash + Clone + Debug,
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: BatchFn<K, V>,
+{
+    pub fn new(load_fn: F) -> Self {
+        Loader::with_cache(load_fn, HashMap::new())
+    }
+}
+
+impl<CK, K, V, F, C> Loader<CK, K, V, F, C>
+where
+    CK: Eq + Hash + Clone + Debug,
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: BatchFn<K, V>,
+    C: Cache<Key = CK, Val = V>,
+{
+    pub fn with_cache(load_fn: F, cache: C) -> Self {
+        let full_keys = FullKeys::default();
+        let load_fn = FullKeyBatchFn {
+            load_fn,
+            full_keys: full_keys.clone(),
+        };
+        Loader {
+            inner: cached::Loader::with_cache(load_fn, cache),
+            full_keys,
+        }
+    }
+
+    pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.inner = self.inner.with_max_batch_size(max_batch_size);
+        self
+    }
+
+    pub fn with_yield_count(mut self, yield_count: usize) -> Self {
+        self.inner = self.inner.with_yield_count(yield_count);
+        self
+    }
+
+    pub fn with_custom_wait_for_work(mut self, wait_for_work_fn: impl WaitForWorkFn) -> Self {
+        self.inner = self.inner.with_custom_wait_for_work(wait_for_work_fn);
+        self
+    }
+
+    /// The underlying loader keyed by cache keys, for priming, clearing and cache-only lookups.
+    pub fn cached(&self) -> &cached::Loader<CK, V, FullKeyBatchFn<CK, K, F>, C> {
+        &self.inner
+    }
+
+    /// Loads `full_key`, identified by `cache_key` for caching and batching. Concurrent loads
+    /// with the same cache key share one request; the first caller's full key is the one sent.
+    pub async fn try_load_with_cache_key(
+        &self,
+        cache_key: CK,
+        full_key: K,
+    ) -> Result<V, LoadError<CK>> {
+        self.full_keys
+            .lock()
+            .unwrap()
+            .entry(cache_key.clone())
+            .or_insert((full_key, 0))
+            .1 += 1;
+        let _claim = Claim {
+            full_keys: &self.full_keys,
+            cache_key: &cache_key,
+        };
+        self.inner.try_load(cache_key.clone()).await
+    }
+
+    pub async fn load_with_cache_key(&self, cache_key: CK, full_key: K) -> V {
+        self.try_load_with_cache_key(cache_key, full_key)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+}
@@ -1,12 +1,58 @@
+mod adaptive;
+pub mod async_cache;
 mod batch_fn;
+pub mod batching;
+pub mod cache;
 pub mod cached;
+mod diagnostics;
+mod error;
+mod event;
+mod instrument;
+pub mod keyed;
+pub mod mapped;
 pub mod non_cached;
+mod rate_limit;
+pub mod registry;
 mod runtime;
+mod semaphore;
+pub mod shared;
+#[cfg(feature = "spill")]
+pub mod spill;
+pub mod stats;
+pub mod wait;
 
-pub use batch_fn::BatchFn;
+pub use batch_fn::{
+    arc_batch_fn, batch_fn, indexed_batch_fn, pairs_batch_fn, ArcBatchFn, BatchFn, FnBatchFn,
+    IndexedBatchFn, PairsBatchFn, SharedBatchFn,
+};
+pub use error::LoadError;
 
 use std::{future::Future, pin::Pin};
 
+pub(crate) const DEFAULT_MAX_BATCH_SIZE: usize = 200;
+pub(crate) const DEFAULT_YIELD_COUNT: usize = 10;
+
+/// Describes how this copy of the crate was compiled, see [`build_info`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildInfo {
+    /// The runtime selected by cargo features, `"async-std"`, `"tokio"` or `"smol"`.
+    pub runtime: &'static str,
+    /// The `max_batch_size` loaders start with.
+    pub default_max_batch_size: usize,
+    /// The number of yields of the default wait-for-work strategy.
+    pub default_yield_count: usize,
+}
+
+/// Reports the active runtime and loader defaults, for diagnostics and for tests that depend
+/// on the runtime feature.
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        runtime: runtime::NAME,
+        default_max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+        default_yield_count: DEFAULT_YIELD_COUNT,
+    }
+}
+
 /// A trait alias. Read as "a function which returns a pinned box containing a future"
 pub trait WaitForWorkFn:
     Fn() -> Pin<Box<dyn Future<Output = ()> + Send + Sync>> + Send + Sync + 'static
@@ -17,14 +63,3 @@ impl<T> WaitForWorkFn for T where
     T: Fn() -> Pin<Box<dyn Future<Output = ()> + Send + Sync>> + Send + Sync + 'static
 {
 }
-
-pub(crate) fn yield_fn(count: usize) -> impl WaitForWorkFn {
-    move || {
-        Box::pin(async move {
-            // yield for other load to append request
-            for _ in 0..count {
-                runtime::yield_now().await;
-            }
-        })
-    }
-}
@@ -0,0 +1,79 @@
+//! A view of a cached loader that transforms each loaded value.
+//!
+//! [`crate::cached::Loader::map_value`] wraps a loader of raw values, e.g. database rows, so
+//! call sites that each need a different projection still share the base loader's cache and
+//! batches.
+use crate::cached::{self, Cache};
+use crate::{BatchFn, LoadError};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::sync::Arc;
+
+pub struct Loader<K, V, W, F, C, G>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: BatchFn<K, V>,
+    C: Cache<Key = K, Val = V>,
+    G: Fn(V) -> W,
+{
+    inner: cached::Loader<K, V, F, C>,
+    f: Arc<G>,
+}
+
+impl<K, V, W, F, C, G> Clone for Loader<K, V, W, F, C, G>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: BatchFn<K, V>,
+    C: Cache<Key = K, Val = V>,
+    G: Fn(V) -> W,
+{
+    fn clone(&self) -> Self {
+        Loader {
+            inner: self.inner.clone(),
+            f: self.f.clone(),
+        }
+    }
+}
+
+impl<K, V, W, F, C, G> Loader<K, V, W, F, C, G>
+where
+    K: Eq + Hash + Clone + Debug,
+    V: Clone,
+    F: BatchFn<K, V>,
+    C: Cache<Key = K, Val = V>,
+    G: Fn(V) -> W,
+{
+    pub(crate) fn new(inner: cached::Loader<K, V, F, C>, f: G) -> Self {
+        Loader {
+            inner,
+            f: Arc::new(f),
+        }
+    }
+
+    /// The loader of untransformed values this one wraps.
+    pub fn base(&self) -> &cached::Loader<K, V, F, C> {
+        &self.inner
+    }
+
+    pub async fn try_load(&self, key: K) -> Result<W, LoadError<K>> {
+        self.inner.try_load(key).await.map(&*self.f)
+    }
+
+    pub async fn load(&self, key: K) -> W {
+        (self.f)(self.inner.load(key).await)
+    }
+
+    pub async fn try_load_many(&self, keys: Vec<K>) -> Result<HashMap<K, W>, LoadError<K>> {
+        let ret = self.inner.try_load_many(keys).await?;
+        Ok(ret.into_iter().map(|(k, v)| (k, (self.f)(v))).collect())
+    }
+
+    pub async fn load_many(&self, keys: Vec<K>) -> HashMap<K, W> {
+        self.try_load_many(keys)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+}
@@ -1,47 +1,59 @@
+use crate::instrument;
 use crate::runtime::{Arc, Mutex};
-use crate::{yield_fn, BatchFn, WaitForWorkFn};
-use std::collections::{HashMap, HashSet};
+use crate::wait::yield_fn;
+use crate::{BatchFn, WaitForWorkFn, DEFAULT_MAX_BATCH_SIZE, DEFAULT_YIELD_COUNT};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
 use std::fmt::Debug;
-use std::hash::Hash;
+use std::hash::{BuildHasher, Hash};
 use std::io::{Error, ErrorKind};
 
 type RequestId = usize;
 
-struct State<K, V> {
-    completed: HashMap<RequestId, V>,
-    failed: HashMap<RequestId, K>,
-    pending: HashMap<RequestId, K>,
+type BatchFullFn = dyn Fn(usize) + Send + Sync;
+
+struct State<K, V, S> {
+    completed: HashMap<RequestId, V, S>,
+    failed: HashMap<RequestId, K, S>,
+    // Distinct keys waiting for the next batch, with every request made for each.
+    pending: HashMap<K, Vec<RequestId>, S>,
     id_seq: RequestId,
 }
 
-impl<K, V> State<K, V> {
-    fn new() -> Self {
+impl<K, V, S: Clone> State<K, V, S> {
+    fn with_hasher(hasher: S) -> Self {
         State {
-            completed: HashMap::new(),
-            failed: HashMap::new(),
-            pending: HashMap::new(),
+            completed: HashMap::with_hasher(hasher.clone()),
+            failed: HashMap::with_hasher(hasher.clone()),
+            pending: HashMap::with_hasher(hasher),
             id_seq: 0,
         }
     }
+}
+
+impl<K, V, S> State<K, V, S> {
     fn next_request_id(&mut self) -> RequestId {
         self.id_seq = self.id_seq.wrapping_add(1);
         self.id_seq
     }
 }
 
-pub struct Loader<K, V, F>
+pub struct Loader<K, V, F, S = RandomState>
 where
     K: Eq + Hash + Clone,
     V: Clone,
     F: BatchFn<K, V>,
 {
-    state: Arc<Mutex<State<K, V>>>,
+    state: Arc<Mutex<State<K, V, S>>>,
     load_fn: Arc<Mutex<F>>,
     wait_for_work_fn: Arc<dyn WaitForWorkFn>,
     max_batch_size: usize,
+    on_batch_full: Option<Arc<BatchFullFn>>,
+    sort_keys: Option<fn(&mut [K])>,
+    preserve_multiplicity: bool,
 }
 
-impl<K, V, F> Clone for Loader<K, V, F>
+impl<K, V, F, S> Clone for Loader<K, V, F, S>
 where
     K: Eq + Hash + Clone,
     V: Clone,
@@ -53,6 +65,9 @@ where
             load_fn: self.load_fn.clone(),
             max_batch_size: self.max_batch_size,
             wait_for_work_fn: self.wait_for_work_fn.clone(),
+            on_batch_full: self.on_batch_full.clone(),
+            sort_keys: self.sort_keys,
+            preserve_multiplicity: self.preserve_multiplicity,
         }
     }
 }
@@ -64,14 +79,36 @@ where
     F: BatchFn<K, V>,
 {
     pub fn new(load_fn: F) -> Loader<K, V, F> {
+        Loader::with_hasher(load_fn, RandomState::new())
+    }
+}
+
+impl<K, V, F, S> Loader<K, V, F, S>
+where
+    K: Eq + Hash + Clone + Debug,
+    V: Clone,
+    F: BatchFn<K, V>,
+    S: BuildHasher,
+{
+    /// Like [`Loader::new`], but hashes the loader's request bookkeeping with `hasher`, e.g. a
+    /// faster non-cryptographic one.
+    pub fn with_hasher(load_fn: F, hasher: S) -> Self
+    where
+        S: Clone,
+    {
         Loader {
-            state: Arc::new(Mutex::new(State::new())),
+            state: Arc::new(Mutex::new(State::with_hasher(hasher))),
             load_fn: Arc::new(Mutex::new(load_fn)),
-            max_batch_size: 200,
-            wait_for_work_fn: Arc::new(yield_fn(10)),
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            wait_for_work_fn: Arc::new(yield_fn(DEFAULT_YIELD_COUNT)),
+            on_batch_full: None,
+            sort_keys: None,
+            preserve_multiplicity: false,
         }
     }
 
+    /// Caps the number of distinct keys per [`BatchFn::load`] call. `0` turns batching off:
+    /// every key is dispatched as soon as it is queued, in a batch of its own.
     pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
         self.max_batch_size = max_batch_size;
         self
@@ -90,34 +127,109 @@ where
         self
     }
 
+    /// Calls `on_batch_full` with the number of queued keys whenever they reach the batch size and
+    /// are dispatched right away, e.g. to log or count batches that suggest the limit is too low.
+    pub fn with_on_batch_full(
+        mut self,
+        on_batch_full: impl Fn(usize) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_batch_full = Some(Arc::new(on_batch_full));
+        self
+    }
+
+    /// Hands a key to the batch function once per request queued for it instead of once per
+    /// batch, e.g. for metrics or weighted prefetching. Each occurrence still resolves from the
+    /// key's single value, and the batch size still counts distinct keys.
+    pub fn with_preserve_multiplicity(mut self, preserve_multiplicity: bool) -> Self {
+        self.preserve_multiplicity = preserve_multiplicity;
+        self
+    }
+
     pub fn max_batch_size(&self) -> usize {
         self.max_batch_size
     }
 
-    pub async fn try_load(&self, key: K) -> Result<V, Error> {
-        let mut state = self.state.lock().await;
-        let request_id = state.next_request_id();
-        state.pending.insert(request_id, key);
-        if state.pending.len() >= self.max_batch_size {
-            let batch = state.pending.drain().collect::<HashMap<usize, K>>();
-            let keys: Vec<K> = batch
-                .values()
+    // Whether enough keys are queued to dispatch them without waiting for more.
+    fn batch_full(&self, state: &State<K, V, S>) -> bool {
+        let queued = state.pending.len();
+        if self.max_batch_size == 0 {
+            return true;
+        }
+        if queued < self.max_batch_size {
+            return false;
+        }
+        if let Some(on_batch_full) = &self.on_batch_full {
+            on_batch_full(queued);
+        }
+        true
+    }
+
+    /// Returns `true` if both handles were cloned from the same loader and so share its state.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+
+    // Loads every pending request, in batches of at most `max_batch_size` distinct keys. Does
+    // nothing if another caller already drained them, so the batch function never sees an empty
+    // batch.
+    async fn dispatch(&self, state: &mut State<K, V, S>) {
+        let limit = self.max_batch_size.max(1);
+        while !state.pending.is_empty() {
+            let batch = if state.pending.len() <= limit {
+                state.pending.drain().collect::<Vec<_>>()
+            } else {
+                let keys = state
+                    .pending
+                    .keys()
+                    .take(limit)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                keys.into_iter()
+                    .filter_map(|k| state.pending.remove_entry(&k))
+                    .collect()
+            };
+            self.load_batch(state, batch).await;
+        }
+    }
+
+    async fn load_batch(&self, state: &mut State<K, V, S>, batch: Vec<(K, Vec<RequestId>)>) {
+        let mut keys: Vec<K> = if self.preserve_multiplicity {
+            batch
+                .iter()
+                .flat_map(|(k, request_ids)| std::iter::repeat_n(k, request_ids.len()))
                 .cloned()
-                .collect::<HashSet<K>>()
-                .into_iter()
-                .collect();
-            let mut load_fn = self.load_fn.lock().await;
-            let load_ret = load_fn.load(keys.as_ref()).await;
-            drop(load_fn);
-            for (request_id, key) in batch.into_iter() {
-                if load_ret
-                    .get(&key)
-                    .and_then(|v| state.completed.insert(request_id, v.clone()))
-                    .is_none()
-                {
-                    state.failed.insert(request_id, key);
+                .collect()
+        } else {
+            batch.iter().map(|(k, _)| k.clone()).collect()
+        };
+        if let Some(sort_keys) = self.sort_keys {
+            sort_keys(&mut keys);
+        }
+        let mut load_fn = self.load_fn.lock().await;
+        let load_ret = instrument::batch(keys.len(), load_fn.load(keys.as_ref())).await;
+        drop(load_fn);
+        for (key, request_ids) in batch.into_iter() {
+            match load_ret.get(&key) {
+                Some(v) => {
+                    for request_id in request_ids {
+                        state.completed.insert(request_id, v.clone());
+                    }
+                }
+                None => {
+                    for request_id in request_ids {
+                        state.failed.insert(request_id, key.clone());
+                    }
                 }
             }
+        }
+    }
+
+    pub async fn try_load(&self, key: K) -> Result<V, Error> {
+        let mut state = self.state.lock().await;
+        let request_id = state.next_request_id();
+        state.pending.entry(key).or_default().push(request_id);
+        if self.batch_full(&state) {
+            self.dispatch(&mut state).await;
             return state.completed.remove(&request_id).ok_or_else(|| {
                 Error::new(
                     ErrorKind::NotFound,
@@ -130,32 +242,12 @@ where
         }
         drop(state);
 
-        (self.wait_for_work_fn)().await;
+        instrument::wait((self.wait_for_work_fn)()).await;
 
         let mut state = self.state.lock().await;
 
         if !state.completed.contains_key(&request_id) {
-            let batch = state.pending.drain().collect::<HashMap<usize, K>>();
-            if !batch.is_empty() {
-                let keys: Vec<K> = batch
-                    .values()
-                    .cloned()
-                    .collect::<HashSet<K>>()
-                    .into_iter()
-                    .collect();
-                let mut load_fn = self.load_fn.lock().await;
-                let load_ret = load_fn.load(keys.as_ref()).await;
-                drop(load_fn);
-                for (request_id, key) in batch.into_iter() {
-                    if load_ret
-                        .get(&key)
-                        .and_then(|v| state.completed.insert(request_id, v.clone()))
-                        .is_none()
-                    {
-                        state.failed.insert(request_id, key);
-                    }
-                }
-            }
+            self.dispatch(&mut state).await;
         }
         state.completed.remove(&request_id).ok_or_else(|| {
             Error::new(
@@ -179,74 +271,43 @@ where
     }
 
     pub async fn try_load_many(&self, keys: Vec<K>) -> Result<HashMap<K, V>, Error> {
+        self.load_many_ordered(keys)
+            .await
+            .into_iter()
+            .map(|(k, v)| v.map(|v| (k, v)))
+            .collect()
+    }
+
+    /// Like [`Self::try_load_many`], but returns one outcome per key in `keys`, in the same order
+    /// and including duplicates, e.g. to build an ordered response. A key that fails doesn't fail
+    /// the others.
+    pub async fn load_many_ordered(&self, keys: Vec<K>) -> Vec<(K, Result<V, Error>)> {
         let mut state = self.state.lock().await;
-        let mut ret = HashMap::new();
         let mut requests = Vec::new();
         for key in keys.into_iter() {
             let request_id = state.next_request_id();
             requests.push((request_id, key.clone()));
-            state.pending.insert(request_id, key);
-            if state.pending.len() >= self.max_batch_size {
-                let batch = state.pending.drain().collect::<HashMap<usize, K>>();
-                let keys: Vec<K> = batch
-                    .values()
-                    .cloned()
-                    .collect::<HashSet<K>>()
-                    .into_iter()
-                    .collect();
-                let mut load_fn = self.load_fn.lock().await;
-                let load_ret = load_fn.load(keys.as_ref()).await;
-                drop(load_fn);
-                for (request_id, key) in batch.into_iter() {
-                    if load_ret
-                        .get(&key)
-                        .and_then(|v| state.completed.insert(request_id, v.clone()))
-                        .is_none()
-                    {
-                        state.failed.insert(request_id, key);
-                    }
-                }
+            state.pending.entry(key).or_default().push(request_id);
+            if self.batch_full(&state) {
+                self.dispatch(&mut state).await;
             }
         }
 
         drop(state);
 
-        (self.wait_for_work_fn)().await;
+        instrument::wait((self.wait_for_work_fn)()).await;
 
         let mut state = self.state.lock().await;
 
-        let mut rest = Vec::new();
-        for (request_id, key) in requests.into_iter() {
-            if let Some(v) = state.completed.remove(&request_id) {
-                ret.insert(key, v);
-            } else {
-                rest.push((request_id, key));
-            }
+        if requests
+            .iter()
+            .any(|(request_id, _)| !state.completed.contains_key(request_id))
+        {
+            self.dispatch(&mut state).await;
         }
-
-        if !rest.is_empty() {
-            let batch = state.pending.drain().collect::<HashMap<usize, K>>();
-            if !batch.is_empty() {
-                let keys: Vec<K> = batch
-                    .values()
-                    .cloned()
-                    .collect::<HashSet<K>>()
-                    .into_iter()
-                    .collect();
-                let mut load_fn = self.load_fn.lock().await;
-                let load_ret = load_fn.load(keys.as_ref()).await;
-                drop(load_fn);
-                for (request_id, key) in batch.into_iter() {
-                    if load_ret
-                        .get(&key)
-                        .and_then(|v| state.completed.insert(request_id, v.clone()))
-                        .is_none()
-                    {
-                        state.failed.insert(request_id, key);
-                    }
-                }
-            }
-            for (request_id, key) in rest.into_iter() {
+        requests
+            .into_iter()
+            .map(|(request_id, key)| {
                 let v = state.completed.remove(&request_id).ok_or_else(|| {
                     Error::new(
                         ErrorKind::NotFound,
@@ -255,12 +316,23 @@ where
                             state.failed.remove(&request_id).expect("failed")
                         ),
                     )
-                })?;
-
-                ret.insert(key, v);
-            }
-        }
+                });
+                (key, v)
+            })
+            .collect()
+    }
+}
 
-        Ok(ret)
+impl<K, V, F, S> Loader<K, V, F, S>
+where
+    K: Eq + Hash + Clone + Debug + Ord,
+    V: Clone,
+    F: BatchFn<K, V>,
+{
+    /// Hands the keys of each batch to the batch function sorted ascending rather than in hash
+    /// order. Keys within a batch are distinct unless [`Self::with_preserve_multiplicity`] is set.
+    pub fn with_sorted_batches(mut self) -> Self {
+        self.sort_keys = Some(<[K]>::sort_unstable);
+        self
     }
 }
@@ -0,0 +1,31 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Spaces calls at least `interval` apart: a token bucket holding a single token that refills
+/// at the configured rate.
+pub(crate) struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    pub(crate) fn per_second(rps: u32) -> Self {
+        RateLimiter {
+            interval: Duration::from_secs(1) / rps.max(1),
+            next: Mutex::new(None),
+        }
+    }
+
+    /// Takes the token if it is available, otherwise returns how long until it is.
+    pub(crate) fn try_acquire(&self) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut next = self.next.lock().unwrap();
+        match *next {
+            Some(at) if at > now => Err(at - now),
+            _ => {
+                *next = Some(now + self.interval);
+                Ok(())
+            }
+        }
+    }
+}
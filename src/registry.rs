@@ -0,0 +1,65 @@
+//! A set of loaders with different key and value types, looked up by type.
+//!
+//! Handy for a request context holding one loader per entity: register each loader once and
+//! fetch it where it's needed with [`LoaderRegistry::get`].
+use crate::cached::{Cache, Loader};
+use crate::BatchFn;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+/// Holds at most one [`Loader`] per loader type, i.e. per combination of key, value, batch
+/// function and cache types.
+#[derive(Default)]
+pub struct LoaderRegistry {
+    loaders: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl LoaderRegistry {
+    pub fn new() -> Self {
+        LoaderRegistry::default()
+    }
+
+    /// Adds `loader`, returning the loader of the same type it replaces, if any.
+    pub fn register<K, V, F, C>(&mut self, loader: Loader<K, V, F, C>) -> Option<Loader<K, V, F, C>>
+    where
+        K: Eq + Hash + Clone + Debug,
+        V: Clone,
+        F: BatchFn<K, V>,
+        C: Cache<Key = K, Val = V>,
+        Loader<K, V, F, C>: Send + Sync + 'static,
+    {
+        self.loaders
+            .insert(TypeId::of::<Loader<K, V, F, C>>(), Box::new(loader))
+            .map(|prev| *prev.downcast().unwrap())
+    }
+
+    /// A handle to the registered loader with the default `HashMap` cache, sharing its cache
+    /// and batches.
+    #[allow(clippy::implicit_hasher)]
+    pub fn get<K, V, F>(&self) -> Option<Loader<K, V, F>>
+    where
+        K: Eq + Hash + Clone + Debug,
+        V: Clone,
+        F: BatchFn<K, V>,
+        Loader<K, V, F>: Send + Sync + 'static,
+    {
+        self.get_with_cache()
+    }
+
+    /// Like [`Self::get`], for a loader with a custom cache.
+    pub fn get_with_cache<K, V, F, C>(&self) -> Option<Loader<K, V, F, C>>
+    where
+        K: Eq + Hash + Clone + Debug,
+        V: Clone,
+        F: BatchFn<K, V>,
+        C: Cache<Key = K, Val = V>,
+        Loader<K, V, F, C>: Send + Sync + 'static,
+    {
+        self.loaders
+            .get(&TypeId::of::<Loader<K, V, F, C>>())
+            .and_then(|loader| loader.downcast_ref::<Loader<K, V, F, C>>())
+            .cloned()
+    }
+}
@@ -1,4 +1,17 @@
+#[cfg(not(any(
+    feature = "runtime-async-std",
+    feature = "runtime-tokio",
+    feature = "runtime-smol"
+)))]
+compile_error!(
+    "dataloader needs a runtime: enable one of the runtime-async-std (default), runtime-tokio or \
+     runtime-smol features"
+);
+
 // runtime-async-std
+#[cfg(feature = "runtime-async-std")]
+pub const NAME: &str = "async-std";
+
 #[cfg(feature = "runtime-async-std")]
 pub type Arc<T> = async_std::sync::Arc<T>;
 
@@ -6,14 +19,80 @@ pub type Arc<T> = async_std::sync::Arc<T>;
 pub type Mutex<T> = async_std::sync::Mutex<T>;
 
 #[cfg(feature = "runtime-async-std")]
-pub use async_std::task::yield_now;
+pub type MutexGuard<'a, T> = async_std::sync::MutexGuard<'a, T>;
+
+#[cfg(feature = "runtime-async-std")]
+pub use async_std::task::{sleep, yield_now};
+
+#[cfg(feature = "runtime-async-std")]
+pub use async_std::future::timeout;
+
+#[cfg(feature = "runtime-async-std")]
+pub fn try_lock<T>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+    mutex.try_lock()
+}
 
 // runtime-tokio
+#[cfg(feature = "runtime-tokio")]
+pub const NAME: &str = "tokio";
+
 #[cfg(feature = "runtime-tokio")]
 pub type Arc<T> = std::sync::Arc<T>;
 
 #[cfg(feature = "runtime-tokio")]
 pub type Mutex<T> = tokio::sync::Mutex<T>;
 
+#[cfg(feature = "runtime-tokio")]
+pub type MutexGuard<'a, T> = tokio::sync::MutexGuard<'a, T>;
+
 #[cfg(feature = "runtime-tokio")]
 pub use tokio::task::yield_now;
+
+#[cfg(feature = "runtime-tokio")]
+pub use tokio::time::{sleep, timeout};
+
+#[cfg(feature = "runtime-tokio")]
+pub fn try_lock<T>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+    mutex.try_lock().ok()
+}
+
+// runtime-smol
+#[cfg(feature = "runtime-smol")]
+pub const NAME: &str = "smol";
+
+#[cfg(feature = "runtime-smol")]
+pub type Arc<T> = std::sync::Arc<T>;
+
+#[cfg(feature = "runtime-smol")]
+pub type Mutex<T> = smol::lock::Mutex<T>;
+
+#[cfg(feature = "runtime-smol")]
+pub type MutexGuard<'a, T> = smol::lock::MutexGuard<'a, T>;
+
+#[cfg(feature = "runtime-smol")]
+pub use smol::future::yield_now;
+
+#[cfg(feature = "runtime-smol")]
+pub async fn sleep(duration: std::time::Duration) {
+    smol::Timer::after(duration).await;
+}
+
+#[cfg(feature = "runtime-smol")]
+pub struct TimeoutError;
+
+#[cfg(feature = "runtime-smol")]
+pub async fn timeout<F: std::future::Future>(
+    duration: std::time::Duration,
+    future: F,
+) -> Result<F::Output, TimeoutError> {
+    smol::future::or(async { Ok(future.await) }, async {
+        sleep(duration).await;
+        Err(TimeoutError)
+    })
+    .await
+}
+
+#[cfg(feature = "runtime-smol")]
+pub fn try_lock<T>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+    mutex.try_lock()
+}
@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll, Waker};
+
+/// Hands out up to a fixed number of permits, parking acquirers until one is released.
+pub(crate) struct Semaphore {
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    permits: usize,
+    // the wakers of the acquires waiting for a permit, by the order they started waiting in
+    waiters: BTreeMap<u64, Waker>,
+    next_waiter: u64,
+}
+
+pub(crate) struct Permit<'a>(&'a Semaphore);
+
+struct Acquire<'a> {
+    semaphore: &'a Semaphore,
+    // set once the acquire waits, and kept when woken so it keeps its place in line
+    waiter: Option<u64>,
+}
+
+impl Semaphore {
+    pub(crate) fn new(permits: usize) -> Self {
+        Semaphore {
+            inner: Mutex::new(Inner {
+                permits: permits.max(1),
+                waiters: BTreeMap::new(),
+                next_waiter: 0,
+            }),
+        }
+    }
+
+    pub(crate) async fn acquire(&self) -> Permit<'_> {
+        Acquire {
+            semaphore: self,
+            waiter: None,
+        }
+        .await
+    }
+}
+
+impl Inner {
+    fn wake_next(&mut self) {
+        if let Some((_, waker)) = self.waiters.pop_first() {
+            waker.wake();
+        }
+    }
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = Permit<'a>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Permit<'a>> {
+        let semaphore = self.semaphore;
+        let mut inner = semaphore.inner.lock().unwrap();
+        if inner.permits > 0 {
+            inner.permits -= 1;
+            if let Some(waiter) = self.waiter.take() {
+                inner.waiters.remove(&waiter);
+            }
+            return Poll::Ready(Permit(semaphore));
+        }
+        let waiter = match self.waiter {
+            Some(waiter) => waiter,
+            None => {
+                inner.next_waiter += 1;
+                inner.next_waiter
+            }
+        };
+        self.waiter = Some(waiter);
+        inner.waiters.insert(waiter, cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        if let Some(waiter) = self.waiter {
+            let mut inner = self.semaphore.inner.lock().unwrap();
+            // woken for a permit it won't take: hand the wake-up on
+            if inner.waiters.remove(&waiter).is_none() && inner.permits > 0 {
+                inner.wake_next();
+            }
+        }
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let mut inner = self.0.inner.lock().unwrap();
+        inner.permits += 1;
+        inner.wake_next();
+    }
+}
@@ -0,0 +1,102 @@
+//! A cached loader for values that aren't `Clone`.
+//!
+//! [`Loader`] caches each value the [`BatchFn`] returns behind an [`Arc`], so handing a value to
+//! a caller only clones the `Arc`. Build on [`crate::arc_batch_fn`] directly to use a custom
+//! [`Cache`] or any other setting of the underlying [`cached::Loader`].
+use crate::cached::{self, Cache};
+use crate::{arc_batch_fn, ArcBatchFn, BatchFn, LoadError, WaitForWorkFn};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::sync::Arc;
+
+pub struct Loader<K, V, F, C = HashMap<K, Arc<V>>>
+where
+    K: Eq + Hash + Clone,
+    F: BatchFn<K, V>,
+    C: Cache<Key = K, Val = Arc<V>>,
+{
+    inner: cached::Loader<K, Arc<V>, ArcBatchFn<F>, C>,
+}
+
+impl<K, V, F, C> Clone for Loader<K, V, F, C>
+where
+    K: Eq + Hash + Clone,
+    F: BatchFn<K, V>,
+    C: Cache<Key = K, Val = Arc<V>>,
+{
+    fn clone(&self) -> Self {
+        Loader {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+#[allow(clippy::implicit_hasher)]
+impl<K, V, F> Loader<K, V, F, HashMap<K, Arc<V>>>
+where
+    K: Eq + Hash + Clone + Debug,
+    F: BatchFn<K, V>,
+{
+    pub fn new(load_fn: F) -> Self {
+        Loader::with_cache(load_fn, HashMap::new())
+    }
+}
+
+impl<K, V, F, C> Loader<K, V, F, C>
+where
+    K: Eq + Hash + Clone + Debug,
+    F: BatchFn<K, V>,
+    C: Cache<Key = K, Val = Arc<V>>,
+{
+    pub fn with_cache(load_fn: F, cache: C) -> Self {
+        Loader {
+            inner: cached::Loader::with_cache(arc_batch_fn(load_fn), cache),
+        }
+    }
+
+    pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.inner = self.inner.with_max_batch_size(max_batch_size);
+        self
+    }
+
+    pub fn with_yield_count(mut self, yield_count: usize) -> Self {
+        self.inner = self.inner.with_yield_count(yield_count);
+        self
+    }
+
+    pub fn with_custom_wait_for_work(mut self, wait_for_work_fn: impl WaitForWorkFn) -> Self {
+        self.inner = self.inner.with_custom_wait_for_work(wait_for_work_fn);
+        self
+    }
+
+    /// The underlying loader of `Arc`-wrapped values, for everything not mirrored here.
+    pub fn cached(&self) -> &cached::Loader<K, Arc<V>, ArcBatchFn<F>, C> {
+        &self.inner
+    }
+
+    pub async fn try_load(&self, key: K) -> Result<Arc<V>, LoadError<K>> {
+        self.inner.try_load(key).await
+    }
+
+    pub async fn load(&self, key: K) -> Arc<V> {
+        self.inner.load(key).await
+    }
+
+    pub async fn try_load_many(&self, keys: Vec<K>) -> Result<HashMap<K, Arc<V>>, LoadError<K>> {
+        self.inner.try_load_many(keys).await
+    }
+
+    pub async fn load_many(&self, keys: Vec<K>) -> HashMap<K, Arc<V>> {
+        self.inner.load_many(keys).await
+    }
+
+    /// Caches `val`, returning the value it replaced, if any.
+    pub async fn prime(&self, key: K, val: V) -> Option<Arc<V>> {
+        self.inner.prime(key, Arc::new(val)).await
+    }
+
+    pub async fn clear(&self, key: K) {
+        self.inner.clear(key).await
+    }
+}
@@ -0,0 +1,206 @@
+//! A [`Cache`] that keeps hot entries in memory and spills cold ones to disk.
+use crate::cached::{Cache, EvictFn};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::hash::Hash;
+use std::io;
+use std::path::PathBuf;
+
+/// Holds up to `capacity` recently used entries in memory. Less recently used ones are
+/// serialized to files in a directory and read back on access, which moves them to memory
+/// again. Meant for jobs whose working set doesn't fit in RAM but still benefits from caching.
+///
+/// Entries that fail to spill are dropped and reported through [`Cache::set_on_evict`]; files
+/// that can't be read back count as misses. The spill files are removed on clear and drop.
+pub struct SpillCache<K, V> {
+    capacity: usize,
+    dir: PathBuf,
+    // in-memory entries with the tick they were last used at
+    front: HashMap<K, (V, u64)>,
+    recency: BTreeMap<u64, K>,
+    tick: u64,
+    // spilled keys with the id of the file holding their value
+    spilled: HashMap<K, u64>,
+    next_file: u64,
+    on_evict: Option<EvictFn<K, V>>,
+}
+
+impl<K, V> SpillCache<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Serialize + DeserializeOwned,
+{
+    /// Creates the cache, spilling to `dir`, which is created if it doesn't exist. The
+    /// directory should be dedicated to this cache.
+    pub fn new(capacity: usize, dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(SpillCache {
+            capacity: capacity.max(1),
+            dir,
+            front: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            spilled: HashMap::new(),
+            next_file: 0,
+            on_evict: None,
+        })
+    }
+
+    /// The number of entries held in memory.
+    pub fn in_memory_len(&self) -> usize {
+        self.front.len()
+    }
+
+    /// The number of entries spilled to disk.
+    pub fn spilled_len(&self) -> usize {
+        self.spilled.len()
+    }
+
+    fn touch(&mut self, key: &K) {
+        self.tick += 1;
+        if let Some((_, used)) = self.front.get_mut(key) {
+            self.recency.remove(used);
+            *used = self.tick;
+            self.recency.insert(self.tick, key.clone());
+        }
+    }
+
+    fn insert_front(&mut self, key: K, val: V) {
+        self.tick += 1;
+        if let Some((_, used)) = self.front.insert(key.clone(), (val, self.tick)) {
+            self.recency.remove(&used);
+        }
+        self.recency.insert(self.tick, key);
+        while self.front.len() > self.capacity {
+            let (_, oldest) = self.recency.pop_first().unwrap();
+            let (val, _) = self.front.remove(&oldest).unwrap();
+            self.spill(oldest, val);
+        }
+    }
+
+    fn spill(&mut self, key: K, val: V) {
+        let file = self.next_file;
+        self.next_file += 1;
+        let written = serde_json::to_vec(&val)
+            .map_err(io::Error::from)
+            .and_then(|bytes| fs::write(self.path(file), bytes));
+        match written {
+            Ok(()) => {
+                self.spilled.insert(key, file);
+            }
+            Err(_) => {
+                if let Some(on_evict) = &self.on_evict {
+                    on_evict(&key, &val);
+                }
+            }
+        }
+    }
+
+    // Reads a spilled value back, removing its file.
+    fn unspill(&mut self, key: &K) -> Option<V> {
+        let file = self.spilled.remove(key)?;
+        let path = self.path(file);
+        let val = fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok());
+        let _ = fs::remove_file(path);
+        val
+    }
+}
+
+impl<K, V> Cache for SpillCache<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Serialize + DeserializeOwned,
+{
+    type Key = K;
+    type Val = V;
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        if self.front.contains_key(key) {
+            self.touch(key);
+        } else {
+            let val = self.unspill(key)?;
+            self.insert_front(key.clone(), val);
+        }
+        self.front.get(key).map(|(val, _)| val)
+    }
+
+    fn insert(&mut self, key: K, val: V) {
+        if let Some(file) = self.spilled.remove(&key) {
+            let _ = fs::remove_file(self.path(file));
+        }
+        self.insert_front(key, val);
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        match self.front.remove(key) {
+            Some((val, used)) => {
+                self.recency.remove(&used);
+                Some(val)
+            }
+            None => self.unspill(key),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.front.clear();
+        self.recency.clear();
+        for (_, file) in self.spilled.drain().collect::<Vec<_>>() {
+            let _ = fs::remove_file(self.path(file));
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.front.len() + self.spilled.len()
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.front.contains_key(key) || self.spilled.contains_key(key)
+    }
+
+    // spilled values are read back to test them, but stay on disk if kept
+    fn retain<P: FnMut(&K, &V) -> bool>(&mut self, mut pred: P) {
+        let recency = &mut self.recency;
+        self.front.retain(|k, (v, used)| {
+            let keep = pred(k, v);
+            if !keep {
+                recency.remove(used);
+            }
+            keep
+        });
+        for (k, file) in std::mem::take(&mut self.spilled) {
+            let path = self.path(file);
+            let keep = fs::read(&path)
+                .ok()
+                .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+                .is_some_and(|v| pred(&k, &v));
+            if keep {
+                self.spilled.insert(k, file);
+            } else {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+
+    fn set_on_evict(&mut self, on_evict: EvictFn<K, V>) {
+        self.on_evict = Some(on_evict);
+    }
+}
+
+impl<K, V> SpillCache<K, V> {
+    fn path(&self, file: u64) -> PathBuf {
+        self.dir.join(format!("{}.json", file))
+    }
+}
+
+impl<K, V> Drop for SpillCache<K, V> {
+    fn drop(&mut self) {
+        for file in self.spilled.values() {
+            let _ = fs::remove_file(self.path(*file));
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! Loader statistics.
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const MAX_BATCH_RECORDS: usize = 4096;
+const MAX_LOOKUP_BUCKETS: usize = 3600;
+
+/// Counters over a loader's lifetime, see [`crate::cached::Loader::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LoaderStats {
+    /// Calls to the batch function.
+    pub batches_dispatched: u64,
+    /// Keys sent to the batch function, over all batches.
+    pub keys_loaded: u64,
+    /// Loaded keys that were served from the cache.
+    pub cache_hits: u64,
+    /// Loaded keys that had to be queued for a batch.
+    pub cache_misses: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct Counters {
+    batches_dispatched: AtomicU64,
+    keys_loaded: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+}
+
+impl Counters {
+    pub(crate) fn record_batch(&self, keys: usize) {
+        self.batches_dispatched.fetch_add(1, Ordering::Relaxed);
+        self.keys_loaded.fetch_add(keys as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_lookups(&self, hits: usize, misses: usize) {
+        self.cache_hits.fetch_add(hits as u64, Ordering::Relaxed);
+        self.cache_misses
+            .fetch_add(misses as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> LoaderStats {
+        LoaderStats {
+            batches_dispatched: self.batches_dispatched.load(Ordering::Relaxed),
+            keys_loaded: self.keys_loaded.load(Ordering::Relaxed),
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            cache_misses: self.cache_misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Loader behavior over a recent time window, see [`crate::cached::Loader::recent_stats`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WindowedStats {
+    /// Batches dispatched within the window.
+    pub batches: usize,
+    /// Mean number of keys per batch, 0 without batches.
+    pub avg_batch_size: f64,
+    /// 99th percentile of the time the batch function took per batch.
+    pub p99_batch_latency: Duration,
+    /// Loaded keys that were served from the cache.
+    pub hits: usize,
+    /// Loaded keys that had to be queued for a batch.
+    pub misses: usize,
+}
+
+impl WindowedStats {
+    /// The share of loaded keys served from the cache, 0 without loads.
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64,
+        }
+    }
+}
+
+struct BatchRecord {
+    at: Instant,
+    size: usize,
+    latency: Duration,
+}
+
+// cache lookups counted per second since the recorder was created
+struct LookupBucket {
+    second: u64,
+    hits: usize,
+    misses: usize,
+}
+
+/// Ring buffers of recent batches and per-second lookup counts. Keeps the last 4096 batches
+/// and the last hour of lookups, so longer windows are truncated.
+pub(crate) struct RecentStats {
+    started: Instant,
+    batches: Mutex<VecDeque<BatchRecord>>,
+    lookups: Mutex<VecDeque<LookupBucket>>,
+}
+
+impl RecentStats {
+    pub(crate) fn new() -> Self {
+        RecentStats {
+            started: Instant::now(),
+            batches: Mutex::new(VecDeque::new()),
+            lookups: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub(crate) fn record_batch(&self, size: usize, latency: Duration) {
+        let mut batches = self.batches.lock().unwrap();
+        if batches.len() == MAX_BATCH_RECORDS {
+            batches.pop_front();
+        }
+        batches.push_back(BatchRecord {
+            at: Instant::now(),
+            size,
+            latency,
+        });
+    }
+
+    pub(crate) fn record_lookups(&self, hits: usize, misses: usize) {
+        if hits + misses == 0 {
+            return;
+        }
+        let second = self.started.elapsed().as_secs();
+        let mut lookups = self.lookups.lock().unwrap();
+        match lookups.back_mut() {
+            Some(bucket) if bucket.second == second => {
+                bucket.hits += hits;
+                bucket.misses += misses;
+            }
+            _ => {
+                if lookups.len() == MAX_LOOKUP_BUCKETS {
+                    lookups.pop_front();
+                }
+                lookups.push_back(LookupBucket {
+                    second,
+                    hits,
+                    misses,
+                });
+            }
+        }
+    }
+
+    pub(crate) fn window(&self, window: Duration) -> WindowedStats {
+        let now = Instant::now();
+        let mut stats = WindowedStats::default();
+
+        let mut latencies = Vec::new();
+        let mut keys = 0;
+        for record in self.batches.lock().unwrap().iter().rev() {
+            if now.duration_since(record.at) > window {
+                break;
+            }
+            keys += record.size;
+            latencies.push(record.latency);
+        }
+        stats.batches = latencies.len();
+        if !latencies.is_empty() {
+            stats.avg_batch_size = keys as f64 / latencies.len() as f64;
+            latencies.sort();
+            let p99 = (latencies.len() * 99).div_ceil(100) - 1;
+            stats.p99_batch_latency = latencies[p99];
+        }
+
+        let now_second = now.duration_since(self.started).as_secs();
+        let first_second = now_second.saturating_sub(window.as_secs());
+        for bucket in self.lookups.lock().unwrap().iter().rev() {
+            if bucket.second < first_second {
+                break;
+            }
+            stats.hits += bucket.hits;
+            stats.misses += bucket.misses;
+        }
+        stats
+    }
+}
@@ -0,0 +1,148 @@
+//! Building blocks for [`WaitForWorkFn`] strategies, passed to `with_custom_wait_for_work`.
+//!
+//! The loaders wait with [`yield_fn`] by default. The combinators build composite strategies,
+//! e.g. "yield up to ten times or one millisecond, whichever is first":
+//!
+//! ```
+//! use dataloader::wait::{race, sleep_fn, yield_fn};
+//! use std::time::Duration;
+//!
+//! let wait = race(yield_fn(10), sleep_fn(Duration::from_millis(1)));
+//! ```
+use crate::runtime;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+pub use crate::WaitForWorkFn;
+
+/// Yields to the runtime `count` times, giving other loads a chance to join the batch.
+pub fn yield_fn(count: usize) -> impl WaitForWorkFn {
+    move || {
+        Box::pin(async move {
+            // yield for other load to append request
+            for _ in 0..count {
+                runtime::yield_now().await;
+            }
+        })
+    }
+}
+
+/// Sleeps for `duration`.
+pub fn sleep_fn(duration: Duration) -> impl WaitForWorkFn {
+    move || Box::pin(runtime::sleep(duration))
+}
+
+/// Waits until either `a` or `b` completes.
+pub fn race(a: impl WaitForWorkFn, b: impl WaitForWorkFn) -> impl WaitForWorkFn {
+    move || {
+        let mut a = a();
+        let mut b = b();
+        Box::pin(std::future::poll_fn(move |cx| {
+            if a.as_mut().poll(cx).is_ready() || b.as_mut().poll(cx).is_ready() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }))
+    }
+}
+
+/// Waits for `a`, then for `b`. `b`'s future is only created once `a` completes, so a
+/// [`sleep_fn`] in second place starts timing then.
+pub fn then(a: impl WaitForWorkFn, b: impl WaitForWorkFn) -> impl WaitForWorkFn {
+    let b = Arc::new(b);
+    move || {
+        let a = a();
+        let b = b.clone();
+        Box::pin(async move {
+            a.await;
+            b().await;
+        })
+    }
+}
+
+/// A signal for [`notified`], fired by the caller to end the wait of every load waiting on it,
+/// e.g. at the end of a GraphQL resolution pass.
+#[derive(Default)]
+pub struct Notify {
+    inner: Mutex<NotifyInner>,
+}
+
+#[derive(Default)]
+struct NotifyInner {
+    // bumped by every notification
+    notified: u64,
+    // the wakers of the waits in progress
+    waiters: HashMap<u64, Waker>,
+    next_waiter: u64,
+}
+
+impl Notify {
+    pub fn new() -> Self {
+        Notify::default()
+    }
+
+    /// Ends every wait that started before this call.
+    pub fn notify(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.notified += 1;
+        for (_, waker) in inner.waiters.drain() {
+            waker.wake();
+        }
+    }
+}
+
+// A wait on a `Notify`, which unregisters its waker when dropped before the notification.
+struct Notified {
+    notify: Arc<Notify>,
+    started: u64,
+    waiter: Option<u64>,
+}
+
+impl Future for Notified {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let notify = self.notify.clone();
+        let mut inner = notify.inner.lock().unwrap();
+        if inner.notified != self.started {
+            self.waiter = None;
+            return Poll::Ready(());
+        }
+        let waiter = match self.waiter {
+            Some(waiter) => waiter,
+            None => {
+                inner.next_waiter += 1;
+                inner.next_waiter
+            }
+        };
+        self.waiter = Some(waiter);
+        inner.waiters.insert(waiter, cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for Notified {
+    fn drop(&mut self) {
+        if let Some(waiter) = self.waiter {
+            self.notify.inner.lock().unwrap().waiters.remove(&waiter);
+        }
+    }
+}
+
+/// Waits until `notify` fires, so keys are only dispatched on the caller's signal, or once a
+/// batch fills up.
+pub fn notified(notify: Arc<Notify>) -> impl WaitForWorkFn {
+    move || {
+        let started = notify.inner.lock().unwrap().notified;
+        Box::pin(Notified {
+            notify: notify.clone(),
+            started,
+            waiter: None,
+        })
+    }
+}
@@ -0,0 +1,197 @@
+use dataloader::async_cache::{AsyncCache, Loader};
+use dataloader::BatchFn;
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone)]
+struct LoadFnWithBatches {
+    batches: Arc<Mutex<Vec<Vec<usize>>>>,
+}
+
+impl BatchFn<usize, usize> for LoadFnWithBatches {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        let mut batch = keys.to_vec();
+        batch.sort();
+        self.batches.lock().unwrap().push(batch);
+        keys.iter().map(|k| (*k, k * 10)).collect()
+    }
+}
+
+// stands in for a networked cache: every operation is a round trip that suspends before it
+// completes, and lookups of `slow_key` take many times longer
+#[derive(Default)]
+struct RemoteCache {
+    entries: Mutex<HashMap<usize, usize>>,
+    round_trips: AtomicUsize,
+    slow_key: Option<usize>,
+}
+
+impl RemoteCache {
+    async fn round_trip(&self, yields: usize) {
+        self.round_trips.fetch_add(1, Ordering::SeqCst);
+        let mut yielded = 0;
+        std::future::poll_fn(|cx| {
+            if yielded == yields {
+                return std::task::Poll::Ready(());
+            }
+            yielded += 1;
+            cx.waker().wake_by_ref();
+            std::task::Poll::Pending
+        })
+        .await
+    }
+}
+
+impl AsyncCache for RemoteCache {
+    type Key = usize;
+    type Val = usize;
+
+    async fn get(&self, key: &usize) -> Option<usize> {
+        let yields = if self.slow_key == Some(*key) { 100 } else { 1 };
+        self.round_trip(yields).await;
+        self.entries.lock().unwrap().get(key).copied()
+    }
+
+    async fn insert(&self, key: usize, val: usize) {
+        self.round_trip(1).await;
+        self.entries.lock().unwrap().insert(key, val);
+    }
+
+    async fn remove(&self, key: &usize) -> Option<usize> {
+        self.round_trip(1).await;
+        self.entries.lock().unwrap().remove(key)
+    }
+
+    async fn clear(&self) {
+        self.round_trip(1).await;
+        self.entries.lock().unwrap().clear();
+    }
+
+    async fn get_many(&self, keys: &[usize]) -> Vec<Option<usize>> {
+        self.round_trip(1).await;
+        let entries = self.entries.lock().unwrap();
+        keys.iter().map(|k| entries.get(k).copied()).collect()
+    }
+}
+
+#[test]
+fn assert_kinds() {
+    fn _assert_send<T: Send>() {}
+    fn _assert_sync<T: Sync>() {}
+    fn _assert_clone<T: Clone>() {}
+    _assert_send::<Loader<usize, usize, LoadFnWithBatches, RemoteCache>>();
+    _assert_sync::<Loader<usize, usize, LoadFnWithBatches, RemoteCache>>();
+    _assert_clone::<Loader<usize, usize, LoadFnWithBatches, RemoteCache>>();
+}
+
+#[test]
+fn test_async_cache() {
+    let load_fn = LoadFnWithBatches {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::with_async_cache(load_fn.clone(), RemoteCache::default());
+
+    let (a, b) = block_on(futures::future::join(loader.load(1), loader.load(2)));
+    assert_eq!((a, b), (10, 20));
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1, 2]]);
+
+    block_on(loader.prime(3, 33));
+    let ret = block_on(loader.load_many(vec![1, 2, 3, 4]));
+    assert_eq!(ret, HashMap::from([(1, 10), (2, 20), (3, 33), (4, 40)]));
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 2);
+    assert_eq!(load_fn.batches.lock().unwrap()[1], vec![4]);
+
+    assert_eq!(block_on(loader.clear(1)), Some(10));
+    assert_eq!(block_on(loader.load(1)), 10);
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 3);
+    block_on(loader.clear_all());
+    assert_eq!(block_on(loader.load(2)), 20);
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 4);
+}
+
+#[test]
+fn test_hits_are_not_held_up_by_a_slow_lookup() {
+    let load_fn = LoadFnWithBatches {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let cache = RemoteCache {
+        slow_key: Some(1),
+        ..RemoteCache::default()
+    };
+    let loader = Loader::with_async_cache(load_fn.clone(), cache);
+    block_on(loader.prime(1, 10));
+    block_on(loader.prime(2, 20));
+
+    let order = Mutex::new(Vec::new());
+    block_on(futures::future::join(
+        async {
+            assert_eq!(loader.load(1).await, 10);
+            order.lock().unwrap().push(1);
+        },
+        async {
+            assert_eq!(loader.load(2).await, 20);
+            order.lock().unwrap().push(2);
+        },
+    ));
+    assert_eq!(*order.lock().unwrap(), vec![2, 1]);
+    assert!(load_fn.batches.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_load_many_round_trips() {
+    let load_fn = LoadFnWithBatches {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let cache = Arc::new(RemoteCache::default());
+    let loader = Loader::with_async_cache(load_fn.clone(), SharedCache(cache.clone()));
+    block_on(loader.prime(1, 10));
+    let before = cache.round_trips.load(Ordering::SeqCst);
+
+    let ret = block_on(loader.load_many(vec![1, 2, 3, 4]));
+    assert_eq!(ret, HashMap::from([(1, 10), (2, 20), (3, 30), (4, 40)]));
+    // one get_many, and insert_many's default of one insert per loaded key
+    assert_eq!(cache.round_trips.load(Ordering::SeqCst) - before, 1 + 3);
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![2, 3, 4]]);
+}
+
+// lets the test read the cache's counters while the loader owns it
+struct SharedCache(Arc<RemoteCache>);
+
+impl AsyncCache for SharedCache {
+    type Key = usize;
+    type Val = usize;
+
+    async fn get(&self, key: &usize) -> Option<usize> {
+        self.0.get(key).await
+    }
+
+    async fn insert(&self, key: usize, val: usize) {
+        self.0.insert(key, val).await
+    }
+
+    async fn remove(&self, key: &usize) -> Option<usize> {
+        self.0.remove(key).await
+    }
+
+    async fn clear(&self) {
+        self.0.clear().await
+    }
+
+    async fn get_many(&self, keys: &[usize]) -> Vec<Option<usize>> {
+        self.0.get_many(keys).await
+    }
+}
+
+#[test]
+fn test_hash_map_async_cache() {
+    let load_fn = LoadFnWithBatches {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone());
+    assert_eq!(block_on(loader.load(1)), 10);
+    assert_eq!(block_on(loader.load(1)), 10);
+    assert_eq!(block_on(loader.load_many(vec![1, 2])).len(), 2);
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 2);
+}
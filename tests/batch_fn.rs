@@ -0,0 +1,145 @@
+use dataloader::cached::Loader;
+use dataloader::non_cached;
+use dataloader::{arc_batch_fn, batch_fn, indexed_batch_fn, pairs_batch_fn, BatchFn};
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::future::ready;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[test]
+fn test_indexed_batch_fn() {
+    let loader = Loader::new(indexed_batch_fn(|keys: Vec<usize>| {
+        let rows = keys
+            .iter()
+            .map(|k| if k % 2 == 0 { Some(k * 10) } else { None })
+            .collect::<Vec<_>>();
+        ready(Ok::<_, String>(rows))
+    }));
+
+    let (v2, v3) = block_on(futures::future::join(
+        loader.try_load(2),
+        loader.try_load(3),
+    ));
+    assert_eq!(v2.unwrap(), Ok(20));
+    assert!(v3.is_err());
+}
+
+#[test]
+fn test_indexed_batch_fn_error() {
+    let loader = Loader::new(indexed_batch_fn(|_keys: Vec<usize>| {
+        ready(Err::<Vec<Option<usize>>, _>("connection reset".to_string()))
+    }));
+
+    let ret = block_on(loader.load_many(vec![1, 2]));
+    assert_eq!(ret[&1], Err("connection reset".to_string()));
+    assert_eq!(ret[&2], Err("connection reset".to_string()));
+}
+
+#[test]
+fn test_indexed_batch_fn_error_non_cached() {
+    let loader = non_cached::Loader::new(indexed_batch_fn(|_keys: Vec<usize>| {
+        ready(Err::<Vec<Option<usize>>, _>("connection reset".to_string()))
+    }))
+    .with_max_batch_size(2);
+
+    let (many, one) = block_on(futures::future::join(
+        loader.load_many(vec![1, 2, 3]),
+        loader.try_load(3),
+    ));
+    assert_eq!(many.len(), 3);
+    assert!(many
+        .values()
+        .all(|v| v == &Err("connection reset".to_string())));
+    assert_eq!(one.unwrap(), Err("connection reset".to_string()));
+}
+
+#[test]
+fn test_pairs_batch_fn() {
+    let loader = Loader::new(pairs_batch_fn(|keys: Vec<usize>| {
+        let rows = keys
+            .into_iter()
+            .filter(|k| *k != 3)
+            .map(|k| (k, k * 10))
+            .collect::<Vec<_>>();
+        ready(rows)
+    }));
+
+    let (v2, v3) = block_on(futures::future::join(
+        loader.try_load(2),
+        loader.try_load(3),
+    ));
+    assert_eq!(v2.unwrap(), 20);
+    assert!(v3.is_err());
+    let ret = block_on(loader.load_many(vec![1, 2, 4]));
+    assert_eq!(ret, HashMap::from([(1, 10), (2, 20), (4, 40)]));
+}
+
+#[derive(Clone, Default)]
+struct Pool {
+    queries: Arc<AtomicUsize>,
+}
+
+impl Pool {
+    async fn query(&self, ids: &[usize]) -> HashMap<usize, String> {
+        self.queries.fetch_add(1, Ordering::SeqCst);
+        ids.iter().map(|id| (*id, format!("row {}", id))).collect()
+    }
+}
+
+#[test]
+fn test_batch_fn_capturing_shared_state() {
+    let pool = Pool::default();
+    let loader = Loader::new(batch_fn({
+        let pool = pool.clone();
+        move |ids: Vec<usize>| {
+            let pool = pool.clone();
+            async move { pool.query(&ids).await }
+        }
+    }))
+    .with_concurrent_dispatch();
+
+    let (a, b) = block_on(futures::future::join(loader.load(1), loader.load(2)));
+    assert_eq!((a.as_str(), b.as_str()), ("row 1", "row 2"));
+    assert_eq!(block_on(loader.load(1)), "row 1");
+    assert_eq!(pool.queries.load(Ordering::SeqCst), 1);
+}
+
+static REPORT_CLONES: AtomicUsize = AtomicUsize::new(0);
+
+struct Report {
+    rows: Vec<String>,
+}
+
+impl Clone for Report {
+    fn clone(&self) -> Self {
+        REPORT_CLONES.fetch_add(1, Ordering::SeqCst);
+        Report {
+            rows: self.rows.clone(),
+        }
+    }
+}
+
+struct ReportBatchFn;
+
+impl BatchFn<usize, Report> for ReportBatchFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, Report> {
+        keys.iter()
+            .map(|k| {
+                let rows = vec![format!("row {}", k); 1000];
+                (*k, Report { rows })
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_arc_batch_fn() {
+    let loader: Loader<usize, Arc<Report>, _> = Loader::new(arc_batch_fn(ReportBatchFn));
+
+    let (a, b) = block_on(futures::future::join(loader.load(1), loader.load(1)));
+    let c = block_on(loader.load(1));
+    assert!(Arc::ptr_eq(&a, &b) && Arc::ptr_eq(&a, &c));
+    assert_eq!(c.rows.len(), 1000);
+    assert_eq!(REPORT_CLONES.load(Ordering::SeqCst), 0);
+}
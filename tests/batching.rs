@@ -0,0 +1,41 @@
+use dataloader::batching::batch_stream;
+use futures::channel::mpsc;
+use futures::StreamExt;
+use std::future::Future;
+use std::time::Duration;
+
+fn run<F: Future>(f: F) -> F::Output {
+    #[cfg(feature = "runtime-tokio")]
+    return tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap()
+        .block_on(f);
+    #[cfg(not(feature = "runtime-tokio"))]
+    futures::executor::block_on(f)
+}
+
+#[test]
+fn test_batch_stream_by_size() {
+    let input = futures::stream::iter(0..10);
+    let batches = run(batch_stream(input, 4, Duration::from_secs(60)).collect::<Vec<_>>());
+    assert_eq!(
+        batches,
+        vec![vec![0, 1, 2, 3], vec![4, 5, 6, 7], vec![8, 9]]
+    );
+}
+
+#[test]
+fn test_batch_stream_by_window() {
+    let (tx, rx) = mpsc::unbounded();
+    tx.unbounded_send(1).unwrap();
+    tx.unbounded_send(2).unwrap();
+
+    let mut batches = batch_stream(rx, 100, Duration::from_millis(10));
+    let first = run(batches.next());
+    assert_eq!(first, Some(vec![1, 2]));
+
+    tx.unbounded_send(3).unwrap();
+    drop(tx);
+    assert_eq!(run(batches.collect::<Vec<_>>()), vec![vec![3]]);
+}
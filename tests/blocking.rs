@@ -0,0 +1,38 @@
+#![cfg(feature = "blocking")]
+
+use dataloader::cached::Loader;
+use dataloader::BatchFn;
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::future::ready;
+
+struct MyLoadFn;
+
+impl BatchFn<usize, usize> for MyLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        let ret = keys.iter().map(|v| (*v, *v)).collect::<HashMap<_, _>>();
+        ready(ret).await
+    }
+}
+
+#[test]
+fn test_get_cached() {
+    let loader = Loader::new(MyLoadFn);
+    assert_eq!(loader.get_cached(&1), None);
+
+    block_on(loader.load(1));
+    block_on(loader.prime(2, 20));
+    assert_eq!(loader.get_cached(&1), Some(1));
+    assert_eq!(loader.get_cached(&2), Some(20));
+    assert_eq!(loader.get_cached(&3), None);
+}
+
+#[test]
+fn test_get_cached_does_not_queue() {
+    let loader = Loader::new(MyLoadFn);
+    assert_eq!(loader.get_cached(&1), None);
+
+    block_on(loader.clear_hard(1));
+    assert_eq!(block_on(loader.load_many(vec![2])).len(), 1);
+    assert_eq!(loader.get_cached(&1), None);
+}
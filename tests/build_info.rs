@@ -0,0 +1,15 @@
+use dataloader::build_info;
+
+#[test]
+fn test_build_info() {
+    let info = build_info();
+    if cfg!(feature = "runtime-tokio") {
+        assert_eq!(info.runtime, "tokio");
+    } else if cfg!(feature = "runtime-smol") {
+        assert_eq!(info.runtime, "smol");
+    } else {
+        assert_eq!(info.runtime, "async-std");
+    }
+    assert_eq!(info.default_max_batch_size, 200);
+    assert_eq!(info.default_yield_count, 10);
+}
@@ -0,0 +1,253 @@
+use dataloader::cache::{BoundedCache, CallbackCache, LruCache, TieredCache, TtlCache};
+use dataloader::cached::{Cache, Loader};
+use dataloader::BatchFn;
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Clone)]
+struct LoadFnWithBatches {
+    batches: Arc<Mutex<Vec<Vec<usize>>>>,
+}
+
+impl LoadFnWithBatches {
+    fn new() -> Self {
+        LoadFnWithBatches {
+            batches: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+impl BatchFn<usize, usize> for LoadFnWithBatches {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        let mut batch = keys.to_vec();
+        batch.sort();
+        self.batches.lock().unwrap().push(batch);
+        keys.iter().map(|k| (*k, *k * 10)).collect()
+    }
+}
+
+#[test]
+fn test_lru_cache() {
+    let mut cache = LruCache::new(2);
+    cache.insert(1, "a");
+    cache.insert(2, "b");
+    assert_eq!(cache.get(&1), Some(&"a"));
+    cache.insert(3, "c");
+    assert_eq!(cache.get(&2), None, "least recently used entry is evicted");
+    assert_eq!(cache.get(&1), Some(&"a"));
+    assert_eq!(cache.get(&3), Some(&"c"));
+    assert_eq!(cache.len(), 2);
+
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    cache.set_on_evict(Box::new({
+        let evicted = evicted.clone();
+        move |k, _| evicted.lock().unwrap().push(*k)
+    }));
+    cache.insert(4, "d");
+    assert_eq!(*evicted.lock().unwrap(), vec![1]);
+    assert_eq!(cache.remove(&3), Some("c"));
+    cache.clear();
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn test_lru_cache_loader() {
+    let load_fn = LoadFnWithBatches::new();
+    let loader = Loader::with_cache(load_fn.clone(), LruCache::new(3));
+
+    block_on(async {
+        loader.prime_many((0..5).map(|k| (k, k))).await;
+        let ret = loader.load_many(vec![0, 1, 2, 3, 4]).await;
+        assert_eq!(ret[&0], 0);
+        assert_eq!(ret[&1], 10);
+        assert_eq!(ret[&2], 2);
+        assert_eq!(ret[&4], 4);
+    });
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![0, 1]]);
+}
+
+#[test]
+fn test_callback_cache_loader() {
+    let dropped = Arc::new(Mutex::new(Vec::new()));
+    let cache = CallbackCache::new(LruCache::new(2), {
+        let dropped = dropped.clone();
+        move |k: &usize, v: &usize| dropped.lock().unwrap().push((*k, *v))
+    });
+    let loader = Loader::with_cache(LoadFnWithBatches::new(), cache);
+
+    block_on(async {
+        for k in 1..=3 {
+            loader.load(k).await;
+        }
+        // capacity eviction of the least recently used entry
+        assert_eq!(*dropped.lock().unwrap(), vec![(1, 10)]);
+        loader.prime(3, 33).await;
+        assert_eq!(*dropped.lock().unwrap(), vec![(1, 10), (3, 30)]);
+
+        loader.clear_all().await;
+        let mut dropped = dropped.lock().unwrap().clone();
+        dropped[2..].sort();
+        assert_eq!(dropped, vec![(1, 10), (3, 30), (2, 20), (3, 33)]);
+    });
+    assert_eq!(block_on(loader.cache_size()), 0);
+}
+
+#[test]
+fn test_callback_cache_keeps_eviction_order() {
+    let dropped = Arc::new(Mutex::new(Vec::new()));
+    let mut cache = CallbackCache::new(BoundedCache::new(2), {
+        let dropped = dropped.clone();
+        move |k: &usize, v: &usize| dropped.lock().unwrap().push((*k, *v))
+    });
+    cache.insert(1, 1);
+    cache.insert(2, 2);
+    cache.insert(1, 11);
+    assert_eq!(*dropped.lock().unwrap(), vec![(1, 1)]);
+    // replacing 1 kept it oldest, so it is evicted first
+    cache.insert(3, 3);
+    assert_eq!(*dropped.lock().unwrap(), vec![(1, 1), (1, 11)]);
+    assert_eq!(cache.get(&2), Some(&2));
+}
+
+#[test]
+fn test_bounded_cache() {
+    let max = 10;
+    let mut cache = BoundedCache::new(max);
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    cache.set_on_evict(Box::new({
+        let evicted = evicted.clone();
+        move |k, _| evicted.lock().unwrap().push(*k)
+    }));
+    for k in 0..max + 5 {
+        // lookups don't save the oldest key from eviction
+        cache.get(&0);
+        cache.insert(k, k * 10);
+    }
+    assert_eq!(cache.len(), max);
+    assert_eq!(*evicted.lock().unwrap(), vec![0, 1, 2, 3, 4]);
+    assert!((0..5).all(|k| cache.get(&k).is_none()));
+    assert!((5..max + 5).all(|k| cache.get(&k) == Some(&(k * 10))));
+
+    cache.remove(&5);
+    cache.insert(6, 0);
+    cache.insert(15, 150);
+    cache.insert(16, 160);
+    assert_eq!(*evicted.lock().unwrap(), vec![0, 1, 2, 3, 4, 6]);
+
+    // retained keys keep their place, and dropped ones are not evictions
+    cache.retain(|k, _| k % 2 == 0);
+    assert_eq!(cache.len(), 5);
+    for k in 17..23 {
+        cache.insert(k, k * 10);
+    }
+    assert_eq!(*evicted.lock().unwrap(), vec![0, 1, 2, 3, 4, 6, 8]);
+
+    // a re-inserted key goes to the back, and its old place is skipped
+    let mut cache = BoundedCache::new(2);
+    cache.insert(1, 10);
+    cache.insert(2, 20);
+    cache.remove(&1);
+    cache.insert(1, 11);
+    cache.insert(3, 30);
+    assert!(cache.get(&2).is_none());
+    assert_eq!(cache.get(&1), Some(&11));
+    assert_eq!(cache.get(&3), Some(&30));
+    for _ in 0..100 {
+        cache.remove(&1);
+        cache.insert(1, 12);
+    }
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.get(&1), Some(&12));
+    assert_eq!(cache.get(&3), Some(&30));
+}
+
+#[test]
+fn test_bounded_cache_holds_at_least_one_entry() {
+    let mut cache = BoundedCache::new(0);
+    assert_eq!(cache.max_entries(), 1);
+    cache.insert(1, "a");
+    assert_eq!(cache.get(&1), Some(&"a"));
+    cache.insert(2, "b");
+    assert_eq!(cache.get(&1), None);
+    assert_eq!(cache.get(&2), Some(&"b"));
+}
+
+#[test]
+fn test_ttl_cache() {
+    let mut cache = TtlCache::new(Duration::from_millis(20));
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    cache.set_on_evict(Box::new({
+        let evicted = evicted.clone();
+        move |k, _| evicted.lock().unwrap().push(*k)
+    }));
+    cache.insert(1, "a");
+    cache.insert(2, "b");
+    assert_eq!(cache.get(&1), Some(&"a"));
+
+    thread::sleep(Duration::from_millis(30));
+    cache.insert(3, "c");
+    assert!(!cache.contains_key(&2));
+    assert!(cache.contains_key(&3));
+    assert_eq!(cache.get(&1), None);
+    assert_eq!(cache.len(), 2);
+    cache.purge_expired();
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache.get(&3), Some(&"c"));
+    assert_eq!(*evicted.lock().unwrap(), vec![1, 2]);
+}
+
+#[test]
+fn test_ttl_cache_huge_ttl_never_expires() {
+    let mut cache = TtlCache::new(Duration::MAX);
+    cache.insert(1, "a");
+    assert!(cache.contains_key(&1));
+    cache.purge_expired();
+    assert_eq!(cache.get(&1), Some(&"a"));
+}
+
+#[test]
+fn test_ttl_cache_loader() {
+    let load_fn = LoadFnWithBatches::new();
+    let loader = Loader::with_cache(load_fn.clone(), TtlCache::new(Duration::from_millis(20)));
+
+    block_on(loader.prime(1, 1));
+    assert_eq!(block_on(loader.try_load(1)).unwrap(), 1);
+    thread::sleep(Duration::from_millis(30));
+    assert_eq!(block_on(loader.try_load(1)).unwrap(), 10);
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1]]);
+}
+
+#[test]
+fn test_tiered_cache() {
+    let mut l2 = HashMap::new();
+    l2.insert(1, "a");
+    let mut cache = TieredCache::new(HashMap::new(), l2);
+
+    assert!(!cache.l1().contains_key(&1));
+    assert_eq!(cache.get(&1), Some(&"a"));
+    assert_eq!(cache.l1().get(&1), Some(&"a"));
+    assert_eq!(cache.get(&2), None);
+
+    cache.insert(2, "b");
+    assert_eq!(cache.l1().get(&2), Some(&"b"));
+    assert_eq!(cache.l2().get(&2), Some(&"b"));
+    assert_eq!(cache.len(), 2);
+
+    cache.retain(|k, _| *k != 1);
+    assert!(!cache.contains_key(&1));
+    assert_eq!(cache.remove(&2), Some("b"));
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn test_tiered_cache_l1_dropping_promoted_entry() {
+    let mut l2 = HashMap::new();
+    l2.insert(1, "a");
+    let mut cache = TieredCache::new(TtlCache::new(Duration::ZERO), l2);
+
+    assert_eq!(cache.get(&1), Some(&"a"));
+    assert!(!cache.l1().contains_key(&1));
+}
@@ -1,23 +1,26 @@
-use dataloader::cached::Loader;
-use dataloader::BatchFn;
+use dataloader::cached::{
+    Cache, EvictFn, IntegrityError, Loader, LoaderBuilder, LoaderConfig, MergePolicy, PrimeSummary,
+};
+use dataloader::stats::{LoaderStats, WindowedStats};
+use dataloader::wait::yield_fn;
+use dataloader::{BatchFn, LoadError};
 use futures::executor::block_on;
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::future::ready;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use std::{panic, thread};
 
 struct MyLoadFn;
 
 impl BatchFn<usize, usize> for MyLoadFn {
     async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
-        let ret = keys
-            .iter()
-            .map(|v| (v.clone(), v.clone()))
-            .collect::<HashMap<_, _>>();
+        let ret = keys.iter().map(|v| (*v, *v)).collect::<HashMap<_, _>>();
         ready(ret).await
     }
 }
 
+#[allow(dead_code)]
 #[derive(Clone)]
 struct Object(usize);
 
@@ -25,7 +28,7 @@ impl BatchFn<usize, Object> for MyLoadFn {
     async fn load(&mut self, keys: &[usize]) -> HashMap<usize, Object> {
         let ret = keys
             .iter()
-            .map(|v| (v.clone(), Object(v.clone())))
+            .map(|v| (*v, Object(*v)))
             .collect::<HashMap<_, _>>();
         ready(ret).await
     }
@@ -43,6 +46,8 @@ fn assert_kinds() {
     _assert_send::<Loader<usize, Object, MyLoadFn>>();
     _assert_sync::<Loader<usize, Object, MyLoadFn>>();
     _assert_clone::<Loader<usize, Object, MyLoadFn>>();
+
+    fn _assert_object_safe(_: &dyn Cache<Key = usize, Val = usize>) {}
 }
 
 #[derive(Clone)]
@@ -54,24 +59,25 @@ struct LoadFnWithHistory<K> {
 impl BatchFn<usize, usize> for LoadFnWithHistory<usize> {
     async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
         // println!("BatchFn load keys {:?}", keys);
-        let mut loaded_keys = self.loaded_keys.lock().unwrap();
-        let mut max_batch_loaded = self.max_batch_loaded.lock().unwrap();
-        if keys.len() > *max_batch_loaded {
-            *max_batch_loaded = keys.len();
-        }
-        for k in keys {
-            if loaded_keys.contains(k) {
-                panic!("already loaded, loader should not request same key");
+        let ret = {
+            let mut loaded_keys = self.loaded_keys.lock().unwrap();
+            let mut max_batch_loaded = self.max_batch_loaded.lock().unwrap();
+            if keys.len() > *max_batch_loaded {
+                *max_batch_loaded = keys.len();
+            }
+            for k in keys {
+                if loaded_keys.contains(k) {
+                    panic!("already loaded, loader should not request same key");
+                }
             }
-        }
 
-        let ret = keys
-            .iter()
-            .map(|v| {
-                loaded_keys.insert(v.clone());
-                (v.clone(), v.clone())
-            })
-            .collect::<HashMap<_, _>>();
+            keys.iter()
+                .map(|v| {
+                    loaded_keys.insert(*v);
+                    (*v, *v)
+                })
+                .collect::<HashMap<_, _>>()
+        };
         ready(ret).await
     }
 }
@@ -173,6 +179,17 @@ fn test_load_unresolved_key() {
     let _ = h1.join().map_err(|e| panic::resume_unwind(e));
 }
 
+#[test]
+fn test_load_or_else() {
+    let loader = Loader::new(LoadFnForEmptyTest);
+    block_on(async {
+        assert_eq!(loader.load_or_else(1337, || 7).await, 7);
+        assert!(!loader.is_cached(&1337).await);
+        loader.prime(1337, 42).await;
+        assert_eq!(loader.load_or_else(1337, || 7).await, 42);
+    });
+}
+
 #[test]
 fn test_try_load_unresolved_key() {
     let load_fn = LoadFnForEmptyTest;
@@ -185,7 +202,7 @@ fn test_try_load_unresolved_key() {
         assert!(fv.is_err())
     });
 
-    let _ = h1.join().unwrap();
+    h1.join().unwrap();
 }
 
 #[test]
@@ -202,7 +219,7 @@ fn test_try_load_unresolved_key_from_multiple_requests() {
         assert!(f2.is_err());
     });
 
-    let _ = h1.join().unwrap();
+    h1.join().unwrap();
 }
 
 #[test]
@@ -222,7 +239,7 @@ fn test_try_load_unresolved_key_from_multiple_requests_beyond_max_batch_size() {
         assert!(f3.is_err());
     });
 
-    let _ = h1.join().unwrap();
+    h1.join().unwrap();
 }
 
 #[test]
@@ -250,3 +267,1158 @@ fn test_load_many() {
         );
     }
 }
+
+#[test]
+fn test_prime_if_absent() {
+    let loader = Loader::new(LoadFnForEmptyTest);
+    block_on(async {
+        assert!(loader.prime_if_absent(1, 1).await);
+        assert!(!loader.prime_if_absent(1, 10).await);
+        assert_eq!(loader.try_load(1).await.unwrap(), 1);
+    });
+}
+
+#[test]
+fn test_prime_many_summary() {
+    let loader = Loader::new(LoadFnForEmptyTest);
+    block_on(loader.prime_many(vec![(1, 1), (2, 2)]));
+
+    let summary = block_on(loader.prime_many(vec![(1, 1), (2, 20), (3, 3)]));
+    assert_eq!(
+        summary,
+        PrimeSummary {
+            inserted: HashSet::from([3]),
+            overwritten: HashSet::from([2]),
+            unchanged: HashSet::from([1]),
+        }
+    );
+    assert_eq!(block_on(loader.try_load(2)).unwrap(), 20);
+    assert_eq!(block_on(loader.try_load(3)).unwrap(), 3);
+}
+
+#[test]
+fn test_prime_many_repeated_keys() {
+    let loader = Loader::new(LoadFnForEmptyTest);
+    block_on(loader.prime_many(vec![(1, 1), (2, 2)]));
+
+    let summary = block_on(loader.prime_many(vec![(1, 10), (3, 3), (3, 30)]));
+    assert_eq!(summary.inserted, HashSet::from([3]));
+    assert_eq!(summary.overwritten, HashSet::from([1]));
+    assert_eq!(block_on(loader.try_load(1)).unwrap(), 10);
+    assert_eq!(block_on(loader.try_load(3)).unwrap(), 30);
+}
+
+#[test]
+fn test_prime_many_primes_unchanged_entries() {
+    let loader = Loader::new(LoadFnForEmptyTest)
+        .with_value_ttl(|_: &usize| Some(Duration::from_millis(200)));
+    block_on(loader.prime_many(vec![(1, 1)]));
+    thread::sleep(Duration::from_millis(120));
+
+    let summary = block_on(loader.prime_many(vec![(1, 1)]));
+    assert_eq!(summary.unchanged, HashSet::from([1]));
+    // re-priming an unchanged entry refreshes its expiry
+    thread::sleep(Duration::from_millis(120));
+    assert!(block_on(loader.is_cached(&1)));
+}
+
+#[test]
+fn test_prime_returns_previous_value() {
+    let loader = Loader::new(LoadFnForEmptyTest);
+    block_on(async {
+        assert_eq!(loader.prime(1, 1).await, None);
+        assert_eq!(loader.prime(1, 10).await, Some(1));
+        assert_eq!(loader.try_load(1).await.unwrap(), 10);
+        loader.clear(1).await;
+        assert_eq!(loader.prime(1, 100).await, None);
+    });
+}
+
+#[derive(Clone)]
+struct StoreFn {
+    stored: Arc<Mutex<HashMap<usize, usize>>>,
+}
+
+impl BatchFn<usize, usize> for StoreFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        let ret = {
+            let stored = self.stored.lock().unwrap();
+            keys.iter()
+                .filter_map(|k| stored.get(k).map(|v| (*k, *v)))
+                .collect::<HashMap<_, _>>()
+        };
+        ready(ret).await
+    }
+
+    async fn store_many(&mut self, values: &[(usize, usize)]) -> Result<(), std::io::Error> {
+        self.stored.lock().unwrap().extend(values.iter().cloned());
+        Ok(())
+    }
+}
+
+#[test]
+fn test_store() {
+    let store_fn = StoreFn {
+        stored: Arc::new(Mutex::new(HashMap::new())),
+    };
+    let loader = Loader::new(store_fn.clone());
+
+    block_on(loader.store(1, 10)).unwrap();
+    assert_eq!(store_fn.stored.lock().unwrap().get(&1), Some(&10));
+
+    store_fn.stored.lock().unwrap().clear();
+    assert_eq!(block_on(loader.try_load(1)).unwrap(), 10);
+}
+
+#[test]
+fn test_store_read_only() {
+    let loader = Loader::new(LoadFnForEmptyTest);
+
+    let err = block_on(loader.store(1, 10)).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+    assert!(block_on(loader.try_load(1)).is_err());
+}
+
+// How the size adapts to latency is covered by the unit tests in src/adaptive.rs.
+#[test]
+fn test_adaptive_batch_size() {
+    let load_fn = LoadFnWithBatches {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone()).with_adaptive_batch_size(4, 64);
+    assert_eq!(loader.max_batch_size(), 64);
+    assert_eq!(loader.current_batch_size(), 4);
+
+    let ret = block_on(loader.load_many((0..500).collect()));
+    assert_eq!(ret.len(), 500);
+    let batches = load_fn.batches.lock().unwrap();
+    assert!(batches.iter().all(|batch| batch.len() <= 64));
+    assert!((4..=64).contains(&loader.current_batch_size()));
+}
+
+#[test]
+fn test_clear_hard() {
+    let load_fn = LoadFnWithHistory {
+        loaded_keys: Arc::new(Mutex::new(HashSet::new())),
+        max_batch_loaded: Arc::new(Mutex::new(0)),
+    };
+    let loader = Loader::new(load_fn.clone());
+    block_on(loader.prime(2, 2));
+
+    let (r1, _) = block_on(futures::future::join(loader.try_load(1), async {
+        loader.clear_hard(1).await;
+        loader.clear_hard(2).await;
+    }));
+    assert_eq!(r1.unwrap_err(), LoadError::KeyNotFound(1));
+    assert!(load_fn.loaded_keys.lock().unwrap().is_empty());
+
+    assert_eq!(block_on(loader.try_load(1)).unwrap(), 1);
+    assert_eq!(block_on(loader.try_load(2)).unwrap(), 2);
+}
+
+#[test]
+fn test_ptr_eq() {
+    let loader = Loader::new(LoadFnForEmptyTest);
+    let cloned = loader.clone().with_max_batch_size(4);
+    let other = Loader::new(LoadFnForEmptyTest);
+
+    assert!(loader.ptr_eq(&cloned));
+    assert!(!loader.ptr_eq(&other));
+}
+
+#[test]
+fn test_try_load_many_into() {
+    let loader = Loader::new(MyLoadFn);
+    let mut out: HashMap<usize, usize> = HashMap::with_capacity(8);
+
+    block_on(loader.try_load_many_into(vec![1, 2], &mut out)).unwrap();
+    block_on(loader.try_load_many_into(vec![3], &mut out)).unwrap();
+    assert_eq!(out.len(), 3);
+    assert_eq!(out[&3], 3);
+
+    out.clear();
+    block_on(loader.try_load_many_into(vec![4], &mut out)).unwrap();
+    assert_eq!(out.keys().collect::<Vec<_>>(), vec![&4]);
+}
+
+#[test]
+fn test_dry_run() {
+    let load_fn = LoadFnWithHistory {
+        loaded_keys: Arc::new(Mutex::new(HashSet::new())),
+        max_batch_loaded: Arc::new(Mutex::new(0)),
+    };
+    let loader = Loader::new(load_fn.clone())
+        .with_max_batch_size(2)
+        .with_dry_run(|_| 0);
+
+    let (v1, v2, v3) = block_on(futures::future::join3(
+        loader.load(1),
+        loader.load(2),
+        loader.load(3),
+    ));
+    assert_eq!((v1, v2, v3), (0, 0, 0));
+    assert!(load_fn.loaded_keys.lock().unwrap().is_empty());
+
+    let mut batches = loader.dry_run_batches();
+    batches.iter_mut().for_each(|b| b.sort());
+    assert_eq!(batches, vec![vec![1, 2], vec![3]]);
+}
+
+#[test]
+fn test_max_keys_per_call() {
+    let load_fn = LoadFnWithHistory {
+        loaded_keys: Arc::new(Mutex::new(HashSet::new())),
+        max_batch_loaded: Arc::new(Mutex::new(0)),
+    };
+    let loader = Loader::new(load_fn.clone()).with_max_keys_per_call(3);
+
+    let err = block_on(loader.try_load_many(vec![1, 2, 3, 4])).unwrap_err();
+    assert_eq!(
+        err,
+        LoadError::TooManyKeys {
+            requested: 4,
+            max: 3
+        }
+    );
+    assert!(load_fn.loaded_keys.lock().unwrap().is_empty());
+
+    let ret = block_on(loader.try_load_many(vec![1, 2, 3])).unwrap();
+    assert_eq!(ret.len(), 3);
+}
+
+struct LoadFnRejectingEmpty;
+
+impl BatchFn<usize, usize> for LoadFnRejectingEmpty {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        assert!(!keys.is_empty(), "dispatched an empty batch");
+        keys.iter().map(|k| (*k, *k)).collect()
+    }
+}
+
+#[test]
+fn test_never_dispatches_empty_batch() {
+    let loader = Loader::new(LoadFnRejectingEmpty).with_max_batch_size(2);
+
+    block_on(async {
+        assert!(loader.load_many(vec![]).await.is_empty());
+        let loads = (0..7).map(|k| loader.load(k % 4));
+        let many = (0..3).map(|k| loader.load_many(vec![k, k + 1, k]));
+        let (values, maps) = futures::future::join(
+            futures::future::join_all(loads),
+            futures::future::join_all(many),
+        )
+        .await;
+        assert_eq!(values, vec![0, 1, 2, 3, 0, 1, 2]);
+        assert!(maps.iter().all(|m| m.len() == 2));
+        assert_eq!(loader.load_many(vec![1, 2]).await.len(), 2);
+    });
+}
+
+// Keeps the `capacity` most recently inserted entries.
+struct FifoCache {
+    capacity: usize,
+    entries: HashMap<usize, usize>,
+    order: VecDeque<usize>,
+    on_evict: Option<EvictFn<usize, usize>>,
+}
+
+impl FifoCache {
+    fn new(capacity: usize) -> Self {
+        FifoCache {
+            capacity,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            on_evict: None,
+        }
+    }
+}
+
+impl Cache for FifoCache {
+    type Key = usize;
+    type Val = usize;
+
+    fn get(&mut self, key: &usize) -> Option<&usize> {
+        self.entries.get(key)
+    }
+
+    fn insert(&mut self, key: usize, val: usize) {
+        if self.entries.insert(key, val).is_none() {
+            self.order.push_back(key);
+        }
+        while self.entries.len() > self.capacity {
+            let oldest = self.order.pop_front().unwrap();
+            if let Some(val) = self.entries.remove(&oldest) {
+                if let Some(on_evict) = &self.on_evict {
+                    on_evict(&oldest, &val);
+                }
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &usize) -> Option<usize> {
+        self.order.retain(|k| k != key);
+        self.entries.remove(key)
+    }
+
+    fn clear(&mut self) {
+        self.order.clear();
+        self.entries.clear();
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn contains_key(&self, key: &usize) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    fn retain<P: FnMut(&usize, &usize) -> bool>(&mut self, mut pred: P) {
+        self.entries.retain(|k, v| pred(k, v));
+        let entries = &self.entries;
+        self.order.retain(|k| entries.contains_key(k));
+    }
+
+    fn set_on_evict(&mut self, on_evict: EvictFn<usize, usize>) {
+        self.on_evict = Some(on_evict);
+    }
+}
+
+#[test]
+fn test_on_evict() {
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    let loader = Loader::with_cache(MyLoadFn, FifoCache::new(2)).with_on_evict({
+        let evicted = evicted.clone();
+        move |k: &usize, v: &usize| evicted.lock().unwrap().push((*k, *v))
+    });
+
+    block_on(async {
+        for k in 1..=4 {
+            loader.load(k).await;
+        }
+        loader.clear(4).await;
+        loader.clear_all().await;
+    });
+    assert_eq!(*evicted.lock().unwrap(), vec![(1, 1), (2, 2)]);
+}
+
+#[test]
+fn test_with_cache_and_config() {
+    let config = LoaderConfig::default()
+        .with_max_batch_size(2)
+        .with_yield_count(5)
+        .with_max_keys_per_call(3);
+    let loader =
+        Loader::<usize, usize, _>::with_cache_and_config(MyLoadFn, HashMap::new(), config).unwrap();
+    assert_eq!(loader.max_batch_size(), 2);
+    assert_eq!(block_on(loader.load(1)), 1);
+    assert!(block_on(loader.try_load_many(vec![1, 2, 3, 4])).is_err());
+
+    let config = LoaderConfig::default().with_adaptive_batch_size(4, 16);
+    let loader =
+        Loader::<usize, usize, _>::with_cache_and_config(MyLoadFn, HashMap::new(), config).unwrap();
+    assert_eq!(loader.max_batch_size(), 16);
+    assert_eq!(loader.current_batch_size(), 4);
+
+    // a max_batch_size of 0 is accepted, like with_max_batch_size(0)
+    let load_fn = LoadFnWithBatches {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let mut config = LoaderConfig::default();
+    config.max_batch_size = 0;
+    let loader = Loader::with_cache_and_config(load_fn.clone(), HashMap::new(), config).unwrap();
+    assert_eq!(loader.max_batch_size(), 0);
+    block_on(loader.load_many(vec![1, 2]));
+    let mut batches = load_fn.batches.lock().unwrap().clone();
+    batches.sort();
+    assert_eq!(batches, vec![vec![1], vec![2]]);
+}
+
+#[test]
+fn test_loader_builder() {
+    let loader = Loader::<usize, usize, _>::builder(MyLoadFn)
+        .with_max_batch_size(2)
+        .with_yield_count(3)
+        .configure(|l| l.with_max_keys_per_call(3))
+        .build()
+        .unwrap();
+    assert_eq!(loader.max_batch_size(), 2);
+    assert_eq!(block_on(loader.load(1)), 1);
+    assert!(block_on(loader.try_load_many(vec![1, 2, 3, 4])).is_err());
+
+    let err = LoaderBuilder::with_cache(MyLoadFn, HashMap::<usize, usize>::new())
+        .with_yield_count(5)
+        .with_custom_wait_for_work(yield_fn(1))
+        .build()
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(err
+        .to_string()
+        .contains("yield_count, custom_wait_for_work"));
+}
+
+#[test]
+fn test_with_cache_and_config_rejects_invalid() {
+    let invalid = [
+        LoaderConfig::default().with_adaptive_batch_size(8, 4),
+        LoaderConfig::default()
+            .with_yield_count(10)
+            .with_batch_window(std::time::Duration::from_millis(1)),
+    ];
+    for config in invalid {
+        let err =
+            Loader::<usize, usize, _>::with_cache_and_config(MyLoadFn, HashMap::new(), config)
+                .err()
+                .unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
+
+#[test]
+fn test_waiters_get_values_evicted_by_tiny_cache() {
+    let loader = Loader::with_cache(MyLoadFn, FifoCache::new(1)).with_max_batch_size(20);
+
+    let (values, many) = block_on(futures::future::join(
+        futures::future::join_all((0..10).map(|k| loader.load(k))),
+        loader.load_many((5..15).collect()),
+    ));
+    assert_eq!(values, (0..10).collect::<Vec<_>>());
+    assert_eq!(many, (5..15).map(|k| (k, k)).collect());
+
+    let loader = Loader::with_cache(MyLoadFn, FifoCache::new(1)).with_max_batch_size(3);
+    let many = block_on(loader.try_load_many((0..10).collect())).unwrap();
+    assert_eq!(many.len(), 10);
+}
+
+#[test]
+fn test_value_ttl_zero_still_delivers() {
+    let loader = Loader::new(MyLoadFn).with_value_ttl(|_: &usize| Some(std::time::Duration::ZERO));
+
+    assert_eq!(block_on(loader.load(1)), 1);
+    assert_eq!(block_on(loader.load_many(vec![1, 2])).len(), 2);
+}
+
+struct LoadFnSkippingMultiplesOf3;
+
+impl BatchFn<usize, usize> for LoadFnSkippingMultiplesOf3 {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        keys.iter()
+            .filter(|k| *k % 3 != 0)
+            .map(|k| (*k, *k))
+            .collect()
+    }
+}
+
+#[test]
+fn test_require_all_keys() {
+    let loader = Loader::new(LoadFnSkippingMultiplesOf3).with_require_all_keys(true);
+
+    let (one, three) = block_on(futures::future::join(
+        loader.try_load(1),
+        loader.try_load(3),
+    ));
+    assert_eq!(one.unwrap(), 1);
+    assert_eq!(
+        three.unwrap_err(),
+        LoadError::Integrity(IntegrityError { missing: vec![3] })
+    );
+
+    match block_on(loader.try_load_many(vec![4, 5, 6, 9])) {
+        Err(LoadError::Integrity(IntegrityError { mut missing })) => {
+            missing.sort();
+            assert_eq!(missing, vec![6, 9]);
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    let loader = Loader::new(LoadFnSkippingMultiplesOf3).with_require_all_keys(false);
+    let err = block_on(loader.try_load(3)).unwrap_err();
+    assert_eq!(err, LoadError::KeyNotFound(3));
+    assert_eq!(err.to_string(), "could not lookup result for given key: 3");
+}
+
+#[test]
+fn test_load_error_downcasts_from_io_error() {
+    let loader = Loader::new(LoadFnSkippingMultiplesOf3).with_require_all_keys(true);
+
+    // converting to io::Error keeps the concrete payload
+    let err = std::io::Error::from(block_on(loader.try_load(12)).unwrap_err());
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    let integrity = err
+        .into_inner()
+        .unwrap()
+        .downcast::<IntegrityError<usize>>()
+        .unwrap();
+    assert_eq!(integrity.missing, vec![12]);
+}
+
+#[test]
+fn test_merge_from() {
+    let parent = Loader::new(MyLoadFn);
+    let child = Loader::new(MyLoadFn);
+
+    block_on(async {
+        parent.prime(1, 10).await;
+        child.prime_many(vec![(1, 100), (2, 200)]).await;
+
+        assert_eq!(
+            parent.merge_from(&child, MergePolicy::KeepExisting).await,
+            1
+        );
+        assert_eq!(parent.load(1).await, 10);
+        assert_eq!(parent.load(2).await, 200);
+
+        assert_eq!(parent.merge_from(&child, MergePolicy::Overwrite).await, 2);
+        assert_eq!(parent.load(1).await, 100);
+
+        assert_eq!(
+            parent
+                .merge_from(&parent.clone(), MergePolicy::Overwrite)
+                .await,
+            0
+        );
+        assert_eq!(
+            child.merge_from(&parent, MergePolicy::KeepExisting).await,
+            0
+        );
+    });
+}
+
+#[derive(Clone)]
+struct LoadFnWithBatches {
+    batches: Arc<Mutex<Vec<Vec<usize>>>>,
+}
+
+impl BatchFn<usize, usize> for LoadFnWithBatches {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        let mut batch = keys.to_vec();
+        batch.sort();
+        self.batches.lock().unwrap().push(batch);
+        keys.iter().map(|k| (*k, *k)).collect()
+    }
+}
+
+#[test]
+fn test_peek() {
+    let load_fn = LoadFnWithBatches {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone());
+
+    block_on(async {
+        loader.prime(1, 10).await;
+        assert_eq!(loader.peek(&1).await, Some(10));
+        assert_eq!(loader.peek(&2).await, None);
+        assert_eq!(loader.load(2).await, 2);
+    });
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![2]]);
+}
+
+#[test]
+fn test_is_cached() {
+    let load_fn = LoadFnWithBatches {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone());
+
+    block_on(async {
+        assert!(!loader.is_cached(&1).await);
+        assert_eq!(loader.load(1).await, 1);
+        assert!(loader.is_cached(&1).await);
+        loader.clear(1).await;
+        assert!(!loader.is_cached(&1).await);
+        assert!(!loader.is_cached(&2).await);
+    });
+    // checking a key never loads it
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1]]);
+}
+
+#[test]
+fn test_load_many_settled() {
+    let loader = Loader::new(LoadFnSkippingMultiplesOf3);
+    block_on(loader.prime(1, 1));
+    let ret = block_on(loader.load_many_settled(vec![1, 2, 3, 6]));
+    assert_eq!(ret.len(), 4);
+    assert_eq!(ret[&1], Ok(1));
+    assert_eq!(ret[&2], Ok(2));
+    assert_eq!(ret[&3], Err(LoadError::KeyNotFound(3)));
+    assert_eq!(ret[&6], Err(LoadError::KeyNotFound(6)));
+
+    let loader = loader.with_max_keys_per_call(1);
+    let ret = block_on(loader.load_many_settled(vec![1, 2]));
+    let too_many = LoadError::TooManyKeys {
+        requested: 2,
+        max: 1,
+    };
+    assert_eq!(ret[&1], Err(too_many.clone()));
+    assert_eq!(ret[&2], Err(too_many));
+}
+
+#[test]
+fn test_clear_where() {
+    let load_fn = LoadFnWithBatches {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone());
+    block_on(async {
+        // values encode a tenant in their tens digit
+        loader
+            .prime_many(vec![(1, 11), (2, 12), (3, 23), (4, 24), (5, 15)])
+            .await;
+        loader.clear_where(|_, v| v / 10 == 1).await;
+        assert_eq!(loader.cache_size().await, 2);
+        assert_eq!(loader.peek(&3).await, Some(23));
+        assert_eq!(loader.peek(&4).await, Some(24));
+        assert_eq!(loader.load(1).await, 1);
+    });
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1]]);
+}
+
+#[test]
+fn test_try_load_many_ordered() {
+    let loader = Loader::<usize, usize, _>::new(MyLoadFn);
+    let ret = block_on(loader.try_load_many_ordered(vec![2, 2, 3]));
+    assert_eq!(ret.unwrap(), vec![2, 2, 3]);
+    let ret = block_on(loader.try_load_many_ordered(vec![3, 1, 2, 1]));
+    assert_eq!(ret.unwrap(), vec![3, 1, 2, 1]);
+
+    let loader = Loader::new(LoadFnSkippingMultiplesOf3);
+    assert!(block_on(loader.try_load_many_ordered(vec![1, 3])).is_err());
+}
+
+#[test]
+fn test_try_load_many_fresh() {
+    let load_fn = LoadFnWithBatches {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone());
+
+    block_on(async {
+        loader.prime(1, 100).await;
+        let fresh = loader.try_load_many_fresh(vec![1, 2]).await.unwrap();
+        assert_eq!(fresh, vec![(1, 1), (2, 2)].into_iter().collect());
+        assert_eq!(loader.load(1).await, 1);
+
+        loader.prime(1, 100).await;
+        let (fresh, other) = futures::future::join(
+            loader.try_load_many_fresh(vec![1, 3]),
+            loader.try_load_many_fresh(vec![3]),
+        )
+        .await;
+        assert_eq!(fresh.unwrap()[&1], 1);
+        assert_eq!(other.unwrap()[&3], 3);
+    });
+    assert_eq!(
+        *load_fn.batches.lock().unwrap(),
+        vec![vec![1, 2], vec![1, 3]]
+    );
+}
+
+#[test]
+fn test_on_resolved() {
+    let loader = Loader::new(LoadFnSkippingMultiplesOf3);
+    let resolved = Arc::new(Mutex::new(Vec::new()));
+    let record = |resolved: &Arc<Mutex<Vec<_>>>| {
+        let resolved = resolved.clone();
+        move |r: &Result<usize, LoadError<usize>>| {
+            resolved.lock().unwrap().push(r.as_ref().ok().copied())
+        }
+    };
+
+    block_on(async {
+        loader.on_resolved(1, record(&resolved)).await;
+        loader.on_resolved(1, record(&resolved)).await;
+        loader.on_resolved(3, record(&resolved)).await;
+        assert!(resolved.lock().unwrap().is_empty());
+
+        let _ = loader.try_load_many(vec![1, 3]).await;
+        resolved.lock().unwrap().sort();
+        assert_eq!(*resolved.lock().unwrap(), vec![None, Some(1), Some(1)]);
+
+        loader.on_resolved(1, record(&resolved)).await;
+        assert_eq!(resolved.lock().unwrap().len(), 4);
+
+        loader.on_resolved(5, record(&resolved)).await;
+        loader.prime(5, 50).await;
+        assert_eq!(resolved.lock().unwrap()[4], Some(50));
+        loader.load(5).await;
+        assert_eq!(resolved.lock().unwrap().len(), 5);
+    });
+}
+
+#[test]
+fn test_unbatched_warning() {
+    let warnings = Arc::new(Mutex::new(Vec::new()));
+    let loader = Loader::<usize, usize, _>::new(MyLoadFn).with_unbatched_warning({
+        let warnings = warnings.clone();
+        move |msg: &str| warnings.lock().unwrap().push(msg.to_string())
+    });
+
+    block_on(async {
+        for k in 0..200usize {
+            loader.load(k).await;
+        }
+    });
+    if cfg!(debug_assertions) {
+        assert_eq!(warnings.lock().unwrap().len(), 1);
+    }
+
+    let loader = Loader::<usize, usize, _>::new(MyLoadFn).with_unbatched_warning({
+        let warnings = warnings.clone();
+        move |msg: &str| warnings.lock().unwrap().push(msg.to_string())
+    });
+    block_on(async {
+        for k in 0..100usize {
+            loader.load_many(vec![3 * k, 3 * k + 1, 3 * k + 2]).await;
+        }
+    });
+    assert!(warnings.lock().unwrap().len() <= 1);
+}
+
+#[test]
+fn test_recent_stats() {
+    let loader = Loader::<usize, usize, _>::new(MyLoadFn).with_recent_stats();
+    let window = Duration::from_secs(60);
+
+    block_on(async {
+        assert_eq!(loader.recent_stats(window), WindowedStats::default());
+        loader.load_many(vec![1, 2, 3, 4]).await;
+        loader.load_many(vec![3, 4, 5, 6]).await;
+        loader.load(1).await;
+    });
+    let stats = loader.recent_stats(window);
+    assert_eq!(stats.batches, 2);
+    assert_eq!(stats.avg_batch_size, 3.0);
+    assert_eq!((stats.hits, stats.misses), (3, 6));
+    assert_eq!(stats.hit_rate(), 3.0 / 9.0);
+
+    let loader = Loader::<usize, usize, _>::new(MyLoadFn);
+    block_on(loader.load(1));
+    assert_eq!(loader.recent_stats(window), WindowedStats::default());
+}
+
+#[test]
+fn test_with_cache_mut() {
+    let loader = Loader::<usize, usize, _>::new(MyLoadFn);
+
+    block_on(async {
+        loader.load_many((0..10).collect()).await;
+        let before = loader
+            .with_cache_mut(|cache| {
+                let len = cache.len();
+                cache.retain(|k, _| k.is_multiple_of(2));
+                len
+            })
+            .await;
+        assert_eq!(before, 10);
+        assert_eq!(loader.with_cache_mut(|cache| cache.len()).await, 5);
+        assert_eq!(
+            loader.prime_many(vec![(1, 1), (2, 2)]).await.inserted.len(),
+            1
+        );
+        loader.with_cache_mut(|cache| cache.insert(42, 0)).await;
+        assert_eq!(loader.try_load_many(vec![42]).await.unwrap()[&42], 0);
+    });
+}
+
+#[test]
+fn test_permanent_cache() {
+    let load_fn = LoadFnWithBatches {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone()).with_permanent_cache(true);
+
+    block_on(async {
+        loader.load_many(vec![1, 2]).await;
+        loader.clear(1).await;
+        loader.clear_hard(2).await;
+        loader.clear_all().await;
+        loader.load_many(vec![1, 2]).await;
+        assert_eq!(load_fn.batches.lock().unwrap().len(), 1);
+
+        loader.force_clear_all().await;
+        loader.load(1).await;
+        assert_eq!(load_fn.batches.lock().unwrap().len(), 2);
+    });
+}
+
+#[derive(Clone)]
+struct FallibleLoadFn {
+    calls: Arc<Mutex<Vec<Vec<usize>>>>,
+}
+
+impl BatchFn<usize, Result<usize, String>> for FallibleLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, Result<usize, String>> {
+        let mut calls = self.calls.lock().unwrap();
+        let mut batch = keys.to_vec();
+        batch.sort();
+        calls.push(batch);
+        // odd keys fail on their first load only
+        let attempt = calls.len();
+        keys.iter()
+            .map(|k| {
+                if k % 2 == 1 && attempt == 1 {
+                    (*k, Err(format!("timeout loading {}", k)))
+                } else {
+                    (*k, Ok(*k))
+                }
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_cache_if() {
+    let load_fn = FallibleLoadFn {
+        calls: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone()).with_cache_if(Result::is_ok);
+
+    block_on(async {
+        let (one, two) = futures::future::join(loader.load(1), loader.load(2)).await;
+        assert_eq!(one, Err("timeout loading 1".to_string()));
+        assert_eq!(two, Ok(2));
+        assert_eq!(loader.load(1).await, Ok(1));
+        assert_eq!(loader.load(2).await, Ok(2));
+    });
+    assert_eq!(*load_fn.calls.lock().unwrap(), vec![vec![1, 2], vec![1]]);
+}
+
+#[test]
+fn test_cache_size() {
+    let loader = Loader::<usize, usize, _>::new(MyLoadFn);
+    block_on(async {
+        assert_eq!(loader.cache_size().await, 0);
+        loader.prime_many((0..5).map(|k| (k, k))).await;
+        loader.prime(4, 40).await;
+        assert_eq!(loader.cache_size().await, 5);
+        loader.clear_all().await;
+        assert_eq!(loader.cache_size().await, 0);
+    });
+
+    let mut cache = FifoCache::new(2);
+    assert!(cache.is_empty());
+    cache.insert(1, 1);
+    cache.insert(2, 2);
+    cache.insert(3, 3);
+    assert_eq!(cache.len(), 2);
+}
+
+#[test]
+fn test_clear_many() {
+    let loader = Loader::<usize, usize, _>::new(MyLoadFn);
+    block_on(async {
+        loader.prime_many((0..5).map(|k| (k, k * 10))).await;
+        loader.clear_many(vec![1, 3]).await;
+        assert_eq!(loader.peek(&1).await, None);
+        assert_eq!(loader.peek(&3).await, None);
+        assert_eq!(loader.peek(&0).await, Some(0));
+        assert_eq!(loader.peek(&2).await, Some(20));
+        assert_eq!(loader.peek(&4).await, Some(40));
+    });
+}
+
+#[test]
+fn test_stats() {
+    let loader = Loader::<usize, usize, _>::new(MyLoadFn);
+    block_on(async {
+        loader.load_many(vec![1, 2, 3]).await;
+        loader.load_many(vec![2, 3, 4]).await;
+        loader.load(1).await;
+        loader.load(5).await;
+    });
+    assert_eq!(
+        loader.stats(),
+        LoaderStats {
+            batches_dispatched: 3,
+            keys_loaded: 5,
+            cache_hits: 3,
+            cache_misses: 5,
+        }
+    );
+    assert_eq!(loader.clone().stats(), loader.stats());
+}
+
+#[test]
+fn test_on_batch_full() {
+    let full = Arc::new(Mutex::new(Vec::new()));
+    let loader = Loader::<usize, usize, _>::new(MyLoadFn)
+        .with_max_batch_size(2)
+        .with_on_batch_full({
+            let full = full.clone();
+            move |size| full.lock().unwrap().push(size)
+        });
+    let ret = block_on(futures::future::join_all((0..5).map(|k| loader.load(k))));
+    assert_eq!(ret, vec![0, 1, 2, 3, 4]);
+    // the fifth key fills no batch and goes out after the wait
+    assert_eq!(*full.lock().unwrap(), vec![2, 2]);
+}
+
+#[test]
+fn test_max_batch_size_zero_disables_batching() {
+    let load_fn = LoadFnWithHistory {
+        loaded_keys: Arc::new(Mutex::new(HashSet::new())),
+        max_batch_loaded: Arc::new(Mutex::new(0)),
+    };
+    let loader = Loader::new(load_fn.clone()).with_max_batch_size(0);
+
+    let (one, two, many) = block_on(futures::future::join3(
+        loader.load(1),
+        loader.load(2),
+        loader.load_many(vec![3, 4, 5]),
+    ));
+    assert_eq!((one, two), (1, 2));
+    assert_eq!(many.len(), 3);
+    assert!(many.iter().all(|(k, v)| k == v));
+    assert_eq!(*load_fn.max_batch_loaded.lock().unwrap(), 1);
+    assert_eq!(load_fn.loaded_keys.lock().unwrap().len(), 5);
+}
+
+#[derive(Clone)]
+struct LoadFnRecordingOrder {
+    batches: Arc<Mutex<Vec<Vec<usize>>>>,
+}
+
+impl BatchFn<usize, usize> for LoadFnRecordingOrder {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        self.batches.lock().unwrap().push(keys.to_vec());
+        keys.iter().map(|k| (*k, *k)).collect()
+    }
+}
+
+#[test]
+fn test_sorted_batches() {
+    let load_fn = LoadFnRecordingOrder {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone())
+        .with_max_batch_size(20)
+        .with_sorted_batches();
+
+    let keys = (0..50).rev().chain(0..10).collect::<Vec<_>>();
+    let ret = block_on(loader.load_many(keys));
+    assert_eq!(ret.len(), 50);
+    let batches = load_fn.batches.lock().unwrap();
+    assert_eq!(batches.iter().map(Vec::len).sum::<usize>(), 50);
+    for batch in batches.iter() {
+        assert!(batch.windows(2).all(|w| w[0] < w[1]), "{:?}", batch);
+    }
+}
+
+struct LoadFnTimes(usize);
+
+impl BatchFn<usize, usize> for LoadFnTimes {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        keys.iter().map(|k| (*k, *k * self.0)).collect()
+    }
+}
+
+#[test]
+fn test_set_load_fn() {
+    let loader = Loader::new(LoadFnTimes(10));
+    block_on(async {
+        assert_eq!(loader.load(1).await, 10);
+        loader.set_load_fn(LoadFnTimes(100)).await;
+        assert_eq!(loader.load(2).await, 200);
+        // the cache survives the swap
+        assert_eq!(loader.load(1).await, 10);
+    });
+}
+
+#[test]
+fn test_load_immediate() {
+    let load_fn = LoadFnWithBatches {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone());
+    block_on(async {
+        assert_eq!(loader.load_immediate(1).await, Ok(1));
+        assert!(loader.is_cached(&1).await);
+        assert_eq!(loader.load_immediate(1).await, Ok(1));
+
+        // a queued key is taken out of the next batch, and its callers get this load's result
+        let (queued, immediate, other) =
+            futures::join!(loader.load(2), loader.load_immediate(2), loader.load(3));
+        assert_eq!((queued, immediate, other), (2, Ok(2), 3));
+    });
+    assert_eq!(
+        *load_fn.batches.lock().unwrap(),
+        vec![vec![1], vec![2], vec![3]]
+    );
+    assert!(block_on(Loader::new(LoadFnForEmptyTest).load_immediate(1)).is_err());
+}
+
+#[test]
+fn test_overlapping_load_many_with_early_dispatch() {
+    let load_fn = LoadFnWithHistory {
+        loaded_keys: Arc::new(Mutex::new(HashSet::new())),
+        max_batch_loaded: Arc::new(Mutex::new(0)),
+    };
+    let loader = Loader::new(load_fn.clone()).with_max_batch_size(3);
+
+    let (a, b) = block_on(futures::future::join(
+        loader.try_load_many((0..10).collect()),
+        loader.try_load_many((5..15).collect()),
+    ));
+    let (a, b) = (a.unwrap(), b.unwrap());
+    assert_eq!(a, (0..10).map(|k| (k, k)).collect::<HashMap<_, _>>());
+    assert_eq!(b, (5..15).map(|k| (k, k)).collect::<HashMap<_, _>>());
+    assert_eq!(load_fn.loaded_keys.lock().unwrap().len(), 15);
+    assert_eq!(*load_fn.max_batch_loaded.lock().unwrap(), 3);
+}
+
+#[test]
+fn test_concurrent_loads_of_one_key_share_a_batch() {
+    for loader in [
+        Loader::new(LoadFnTimes(10)),
+        Loader::new(LoadFnTimes(10)).with_max_batch_size(1),
+        Loader::new(LoadFnTimes(10)).with_yield_count(0),
+    ] {
+        let (a, b) = block_on(futures::future::join(
+            loader.try_load(1),
+            loader.try_load(1),
+        ));
+        assert_eq!((a.unwrap(), b.unwrap()), (10, 10));
+        let stats = loader.stats();
+        assert_eq!(stats.batches_dispatched, 1);
+        assert_eq!(stats.keys_loaded, 1);
+    }
+}
+
+#[test]
+fn test_initial_capacity() {
+    let loader = Loader::new(LoadFnTimes(10)).with_initial_capacity(64);
+    assert!(block_on(loader.with_cache_mut(|cache| cache.capacity())) >= 64);
+    let ret = block_on(loader.load_many((0..100).collect()));
+    assert_eq!(ret.len(), 100);
+    assert_eq!(ret[&99], 990);
+
+    // caches without `Cache::reserve` ignore the hint
+    let loader = Loader::with_cache(LoadFnTimes(10), FifoCache::new(2)).with_initial_capacity(64);
+    assert_eq!(block_on(loader.load(3)), 30);
+}
+
+#[test]
+fn test_builder_settings_wait_for_a_locked_state() {
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    let loader = Loader::with_cache(MyLoadFn, FifoCache::new(2));
+    // the state is locked while `with_cache_mut` runs, so the settings apply on the next lock
+    let loader = block_on(loader.with_cache_mut(|_| {
+        loader.clone().with_on_evict({
+            let evicted = evicted.clone();
+            move |k: &usize, v: &usize| evicted.lock().unwrap().push((*k, *v))
+        })
+    }));
+    block_on(async {
+        for k in 1..=3 {
+            loader.load(k).await;
+        }
+    });
+    assert_eq!(*evicted.lock().unwrap(), vec![(1, 1)]);
+
+    let loader = Loader::new(LoadFnTimes(10));
+    let loader = block_on(loader.with_cache_mut(|_| loader.clone().with_initial_capacity(64)));
+    assert!(block_on(loader.with_cache_mut(|cache| cache.capacity())) >= 64);
+}
+
+#[test]
+fn test_fork() {
+    let loader = Loader::new(LoadFnTimes(10)).with_max_batch_size(5);
+    block_on(async {
+        loader.prime(0, 0).await;
+        let (a, b) = (loader.fork().await, loader.fork().await);
+        assert!(!a.ptr_eq(&b) && !a.ptr_eq(&loader));
+        assert_eq!(a.max_batch_size(), 5);
+        assert!(!a.is_cached(&0).await);
+
+        assert_eq!(a.load(1).await, 10);
+        assert!(a.is_cached(&1).await);
+        assert!(!b.is_cached(&1).await);
+        assert!(!loader.is_cached(&1).await);
+    });
+}
+
+struct LoadFnPanickingOn13;
+
+impl BatchFn<usize, usize> for LoadFnPanickingOn13 {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        if keys.contains(&13) {
+            panic!("unlucky batch");
+        }
+        keys.iter().map(|k| (*k, *k)).collect()
+    }
+}
+
+#[test]
+fn test_batch_panicked() {
+    let loader = Loader::new(LoadFnPanickingOn13);
+    let (a, b) = block_on(futures::future::join(
+        loader.try_load(13),
+        loader.try_load(1),
+    ));
+    assert_eq!(a, Err(LoadError::BatchPanicked(13)));
+    assert_eq!(b, Err(LoadError::BatchPanicked(1)));
+
+    // the loader is still usable, and the panicked keys weren't cached
+    assert_eq!(block_on(loader.try_load(1)), Ok(1));
+    assert_eq!(
+        block_on(loader.try_load_many(vec![2, 3])).unwrap(),
+        HashMap::from([(2, 2), (3, 3)])
+    );
+    assert!(!block_on(loader.is_cached(&13)));
+}
+
+#[test]
+fn test_load_many_duplicate_keys() {
+    let load_fn = LoadFnWithBatches {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone());
+    let keys = (0..5).cycle().take(100).collect::<Vec<_>>();
+
+    let ret = block_on(loader.try_load_many(keys.clone())).unwrap();
+    assert_eq!(ret, (0..5).map(|k| (k, k)).collect::<HashMap<_, _>>());
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![0, 1, 2, 3, 4]]);
+    let stats = loader.stats();
+    assert_eq!((stats.cache_hits, stats.cache_misses), (0, 5));
+
+    let ret = block_on(loader.try_load_many_ordered(keys.clone())).unwrap();
+    assert_eq!(ret, keys);
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn test_prewarm() {
+    let load_fn = LoadFnWithBatches {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone()).with_max_batch_size(200);
+    block_on(async {
+        loader.prime(0, 0).await;
+        loader.prewarm((0..=500).collect()).await;
+        let batches = load_fn.batches.lock().unwrap().clone();
+        assert_eq!(
+            batches.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![200, 200, 100]
+        );
+        assert!(!batches.iter().flatten().any(|k| *k == 0));
+
+        assert_eq!(loader.load(250).await, 250);
+        assert_eq!(loader.load_many((0..=500).collect()).await.len(), 501);
+        assert_eq!(loader.stats().batches_dispatched, 3);
+    });
+}
+
+#[test]
+fn test_load_many_chunks_large_input() {
+    let load_fn = LoadFnWithBatches {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone()).with_max_batch_size(100);
+    let ret = block_on(loader.try_load_many((0..1000).collect())).unwrap();
+    assert_eq!(ret.len(), 1000);
+    let batches = load_fn.batches.lock().unwrap();
+    assert!(batches.iter().all(|b| b.len() <= 100));
+    assert_eq!(batches.iter().map(Vec::len).sum::<usize>(), 1000);
+}
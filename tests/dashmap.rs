@@ -0,0 +1,87 @@
+#![cfg(feature = "dashmap")]
+
+use dataloader::cache::DashMapCache;
+use dataloader::cached::{Cache, Loader};
+use dataloader::BatchFn;
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+#[derive(Clone)]
+struct LoadFnWithBatches {
+    batches: Arc<Mutex<Vec<Vec<usize>>>>,
+}
+
+impl BatchFn<usize, usize> for LoadFnWithBatches {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        self.batches.lock().unwrap().push(keys.to_vec());
+        keys.iter().map(|k| (*k, *k * 10)).collect()
+    }
+}
+
+#[test]
+fn test_dashmap_cache() {
+    let mut cache = DashMapCache::new();
+    cache.insert(1, "a");
+    cache.insert(2, "b");
+    assert_eq!(cache.get(&1), Some(&"a"));
+    assert!(cache.contains_key(&2));
+    assert_eq!(cache.remove(&2), Some("b"));
+    assert_eq!(cache.get(&2), None);
+    assert_eq!(cache.len(), 1);
+
+    let reader = cache.concurrent_reader().unwrap();
+    assert_eq!(reader(&1), Some("a"));
+    cache.clear();
+    assert_eq!(reader(&1), None);
+}
+
+#[test]
+fn test_dashmap_cache_many_threads() {
+    let load_fn = LoadFnWithBatches {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::with_cache(load_fn.clone(), DashMapCache::new());
+
+    let threads = (0..8)
+        .map(|t| {
+            let loader = loader.clone();
+            thread::spawn(move || {
+                block_on(async {
+                    for round in 0..50 {
+                        for k in 0..100 {
+                            let k = (k + t * 13 + round) % 100;
+                            assert_eq!(loader.load(k).await, k * 10);
+                        }
+                    }
+                })
+            })
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    // every key was loaded exactly once, however the threads raced
+    let mut loaded = load_fn.batches.lock().unwrap().concat();
+    loaded.sort();
+    assert_eq!(loaded, (0..100).collect::<Vec<_>>());
+    let stats = loader.stats();
+    assert_eq!(stats.cache_hits + stats.cache_misses, 8 * 50 * 100);
+    assert_eq!(block_on(loader.cache_size()), 100);
+}
+
+#[test]
+fn test_dashmap_cache_fork() {
+    let load_fn = LoadFnWithBatches {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::with_cache(load_fn.clone(), DashMapCache::new());
+    assert_eq!(block_on(loader.load(1)), 10);
+
+    // the fork reads its own cache, not the parent's
+    let fork = block_on(loader.fork());
+    assert_eq!(block_on(fork.load(1)), 10);
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 2);
+}
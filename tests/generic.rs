@@ -34,7 +34,7 @@ where
 {
     async fn load(&mut self, keys: &[ObjectId]) -> HashMap<ObjectId, Option<T>> {
         println!("load batch {:?}", keys);
-        T::load_many(&keys).await
+        T::load_many(keys).await
     }
 }
 
@@ -45,3 +45,33 @@ fn test_generic() {
     let my_model: HashMap<ObjectId, Option<MyModel>> = block_on(f);
     println!("{:?}", my_model);
 }
+
+struct Dictionary<'a> {
+    words: &'a HashMap<String, String>,
+}
+
+impl<'a> BatchFn<&'a str, &'a str> for Dictionary<'a> {
+    async fn load(&mut self, keys: &[&'a str]) -> HashMap<&'a str, &'a str> {
+        let ret = keys
+            .iter()
+            .filter_map(|k| self.words.get(*k).map(|v| (*k, v.as_str())))
+            .collect();
+        ready(ret).await
+    }
+}
+
+#[test]
+fn test_borrowed_keys_and_values() {
+    let words = vec![("one", "uno"), ("two", "dos")]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect::<HashMap<_, _>>();
+    let requested = ["one".to_string(), "two".to_string()];
+
+    let loader = Loader::new(Dictionary { words: &words });
+    let f = futures::future::join(
+        loader.load(requested[0].as_str()),
+        loader.load(requested[1].as_str()),
+    );
+    assert_eq!(block_on(f), ("uno", "dos"));
+}
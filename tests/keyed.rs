@@ -0,0 +1,79 @@
+use dataloader::keyed::Loader;
+use dataloader::BatchFn;
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+static HASHES: AtomicUsize = AtomicUsize::new(0);
+
+// A composite key that counts how often it is hashed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Query {
+    id: u32,
+    fields: Vec<String>,
+}
+
+impl Hash for Query {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        HASHES.fetch_add(1, Ordering::SeqCst);
+        self.id.hash(state);
+        self.fields.hash(state);
+    }
+}
+
+fn query(id: u32) -> Query {
+    Query {
+        id,
+        fields: vec!["name".to_string(), "email".to_string()],
+    }
+}
+
+#[derive(Clone)]
+struct QueryLoadFn {
+    batches: Arc<Mutex<Vec<Vec<u32>>>>,
+}
+
+impl BatchFn<Query, String> for QueryLoadFn {
+    async fn load(&mut self, keys: &[Query]) -> HashMap<Query, String> {
+        self.batches
+            .lock()
+            .unwrap()
+            .push(keys.iter().map(|q| q.id).collect());
+        keys.iter()
+            .filter(|q| q.id != 0)
+            .map(|q| (q.clone(), format!("user {} {:?}", q.id, q.fields)))
+            .collect()
+    }
+}
+
+#[test]
+fn test_load_with_cache_key() {
+    let load_fn = QueryLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone());
+
+    let (a, b, c) = block_on(futures::future::join3(
+        loader.load_with_cache_key(1, query(1)),
+        loader.load_with_cache_key(2, query(2)),
+        loader.load_with_cache_key(1, query(1)),
+    ));
+    assert_eq!(a, "user 1 [\"name\", \"email\"]");
+    assert!(b.starts_with("user 2"));
+    assert_eq!(a, c);
+    let mut batches = load_fn.batches.lock().unwrap().clone();
+    batches[0].sort();
+    assert_eq!(batches, vec![vec![1, 2]]);
+
+    // cache hits only hash the cache key
+    let hashes = HASHES.load(Ordering::SeqCst);
+    assert_eq!(block_on(loader.load_with_cache_key(2, query(2))), b);
+    assert_eq!(HASHES.load(Ordering::SeqCst), hashes);
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 1);
+
+    assert!(block_on(loader.try_load_with_cache_key(0, query(0))).is_err());
+    block_on(loader.cached().prime(0, "primed".to_string()));
+    assert_eq!(block_on(loader.load_with_cache_key(0, query(0))), "primed");
+}
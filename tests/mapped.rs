@@ -0,0 +1,63 @@
+use dataloader::cached::Loader;
+use dataloader::BatchFn;
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Debug, PartialEq)]
+struct Row {
+    id: usize,
+    name: String,
+}
+
+#[derive(Clone)]
+struct RowLoadFn {
+    batches: Arc<Mutex<Vec<Vec<usize>>>>,
+}
+
+impl BatchFn<usize, Row> for RowLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, Row> {
+        let mut batch = keys.to_vec();
+        batch.sort();
+        self.batches.lock().unwrap().push(batch);
+        keys.iter()
+            .map(|k| {
+                let row = Row {
+                    id: *k,
+                    name: format!("row {}", k),
+                };
+                (*k, row)
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn assert_kinds() {
+    let loader = Loader::new(RowLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    });
+    fn _assert_send_sync_clone<T: Send + Sync + Clone>(_: &T) {}
+    _assert_send_sync_clone(&loader.map_value(|row: Row| row.id));
+}
+
+#[test]
+fn test_map_value() {
+    let load_fn = RowLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone());
+    let ids = loader.map_value(|row: Row| row.id);
+    let names = loader.map_value(|row: Row| row.name);
+
+    let (id, name) = block_on(futures::future::join(ids.load(1), names.load(2)));
+    assert_eq!((id, name.as_str()), (1, "row 2"));
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1, 2]]);
+
+    // the projections read the base loader's cache
+    assert_eq!(block_on(names.try_load(1)).unwrap(), "row 1");
+    let many = block_on(ids.load_many(vec![1, 2, 3]));
+    assert_eq!(many, HashMap::from([(1, 1), (2, 2), (3, 3)]));
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 2);
+    assert!(ids.base().ptr_eq(&loader));
+}
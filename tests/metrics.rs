@@ -0,0 +1,97 @@
+#![cfg(feature = "metrics")]
+
+use dataloader::{cached, non_cached, BatchFn};
+use futures::executor::block_on;
+use metrics::{
+    Counter, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder, SharedString, Unit,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+struct MyLoadFn;
+
+impl BatchFn<usize, usize> for MyLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        keys.iter().map(|k| (*k, *k)).collect()
+    }
+}
+
+type Values = Arc<Mutex<Vec<(String, f64)>>>;
+
+struct RecordedHistogram {
+    name: String,
+    values: Values,
+}
+
+impl HistogramFn for RecordedHistogram {
+    fn record(&self, value: f64) {
+        self.values.lock().unwrap().push((self.name.clone(), value));
+    }
+}
+
+// Records every histogram value with the name of its metric.
+#[derive(Default)]
+struct HistogramRecorder {
+    values: Values,
+}
+
+impl HistogramRecorder {
+    fn values(&self, name: &str) -> Vec<f64> {
+        let values = self.values.lock().unwrap();
+        values
+            .iter()
+            .filter(|(n, _)| n == name)
+            .map(|(_, v)| *v)
+            .collect()
+    }
+}
+
+impl Recorder for HistogramRecorder {
+    fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+    fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+    fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+    fn register_counter(&self, _: &Key, _: &Metadata<'_>) -> Counter {
+        Counter::noop()
+    }
+
+    fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+        Gauge::noop()
+    }
+
+    fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+        Histogram::from_arc(Arc::new(RecordedHistogram {
+            name: key.name().to_string(),
+            values: self.values.clone(),
+        }))
+    }
+}
+
+#[test]
+fn test_cached_batch_size() {
+    let recorder = HistogramRecorder::default();
+    metrics::with_local_recorder(&recorder, || {
+        let loader = cached::Loader::new(MyLoadFn).with_max_batch_size(3);
+        block_on(loader.load_many((0..7).collect()));
+        block_on(loader.load(10));
+    });
+    let mut sizes = recorder.values("dataloader.batch.size");
+    sizes.sort_by(f64::total_cmp);
+    assert_eq!(sizes, vec![1.0, 1.0, 3.0, 3.0]);
+    let waits = recorder.values("dataloader.wait.duration");
+    assert_eq!(waits.len(), 2);
+    assert!(waits.iter().all(|w| *w >= 0.0));
+}
+
+#[test]
+fn test_non_cached_batch_size() {
+    let recorder = HistogramRecorder::default();
+    metrics::with_local_recorder(&recorder, || {
+        let loader = non_cached::Loader::new(MyLoadFn);
+        block_on(loader.load_many(vec![1, 2, 3]));
+    });
+    assert_eq!(recorder.values("dataloader.batch.size"), vec![3.0]);
+    assert_eq!(recorder.values("dataloader.wait.duration").len(), 1);
+}
@@ -3,6 +3,7 @@ use dataloader::BatchFn;
 use futures::executor::block_on;
 use std::collections::HashMap;
 use std::future::ready;
+use std::hash::{BuildHasher, Hasher};
 use std::sync::{Arc, Mutex};
 use std::{panic, thread};
 
@@ -10,10 +11,7 @@ struct MyLoadFn;
 
 impl BatchFn<usize, usize> for MyLoadFn {
     async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
-        let ret = keys
-            .iter()
-            .map(|v| (v.clone(), v.clone()))
-            .collect::<HashMap<_, _>>();
+        let ret = keys.iter().map(|v| (*v, *v)).collect::<HashMap<_, _>>();
         ready(ret).await
     }
 }
@@ -26,7 +24,7 @@ impl BatchFn<usize, Object> for MyLoadFn {
     async fn load(&mut self, keys: &[usize]) -> HashMap<usize, Object> {
         let ret = keys
             .iter()
-            .map(|v| (v.clone(), Object(v.clone())))
+            .map(|v| (*v, Object(*v)))
             .collect::<HashMap<_, _>>();
         ready(ret).await
     }
@@ -54,14 +52,13 @@ struct LoadFnWithHistory {
 impl BatchFn<usize, usize> for LoadFnWithHistory {
     async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
         // println!("BatchFn load keys {:?}", keys);
-        let mut max_batch_loaded = self.max_batch_loaded.lock().unwrap();
-        if keys.len() > *max_batch_loaded {
-            *max_batch_loaded = keys.len();
+        {
+            let mut max_batch_loaded = self.max_batch_loaded.lock().unwrap();
+            if keys.len() > *max_batch_loaded {
+                *max_batch_loaded = keys.len();
+            }
         }
-        let ret = keys
-            .iter()
-            .map(|v| (v.clone(), v.clone()))
-            .collect::<HashMap<_, _>>();
+        let ret = keys.iter().map(|v| (*v, *v)).collect::<HashMap<_, _>>();
         ready(ret).await
     }
 }
@@ -173,7 +170,7 @@ fn test_load_safe_unresolved_key() {
         assert!(fv.is_err())
     });
 
-    let _ = h1.join().unwrap();
+    h1.join().unwrap();
 }
 
 #[test]
@@ -191,7 +188,7 @@ fn test_try_load_unresolved_key_multiple_requests() {
         assert!(f2.is_err());
     });
 
-    let _ = h1.join().unwrap();
+    h1.join().unwrap();
 }
 
 #[test]
@@ -211,7 +208,7 @@ fn test_try_load_unresolved_key_multiple_requests_beyond_max_batch_size() {
         assert!(f3.is_err());
     });
 
-    let _ = h1.join().unwrap();
+    h1.join().unwrap();
 }
 
 #[test]
@@ -237,3 +234,227 @@ fn test_load_many() {
         );
     }
 }
+
+#[test]
+fn test_load_many_ordered() {
+    let loader = Loader::new(MyLoadFn);
+    let ret = block_on(loader.load_many_ordered(vec![3, 1, 3]));
+    let ret = ret
+        .into_iter()
+        .map(|(k, v)| (k, v.unwrap()))
+        .collect::<Vec<(usize, usize)>>();
+    assert_eq!(ret, vec![(3, 3), (1, 1), (3, 3)]);
+
+    let loader = Loader::new(LoadFnForEmptyTest);
+    let ret = block_on(loader.load_many_ordered(vec![2, 2]));
+    assert_eq!(ret.len(), 2);
+    assert!(ret
+        .iter()
+        .all(|(k, v)| *k == 2 && v.as_ref().unwrap_err().kind() == std::io::ErrorKind::NotFound));
+}
+
+#[test]
+fn test_ptr_eq() {
+    let loader = Loader::new(LoadFnForEmptyTest);
+    let cloned = loader.clone();
+    let other = Loader::new(LoadFnForEmptyTest);
+
+    assert!(loader.ptr_eq(&cloned));
+    assert!(!loader.ptr_eq(&other));
+}
+
+struct LoadFnRejectingEmpty;
+
+impl BatchFn<usize, usize> for LoadFnRejectingEmpty {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        assert!(!keys.is_empty(), "dispatched an empty batch");
+        keys.iter().map(|k| (*k, *k)).collect()
+    }
+}
+
+#[test]
+fn test_never_dispatches_empty_batch() {
+    let loader = Loader::new(LoadFnRejectingEmpty).with_max_batch_size(2);
+
+    block_on(async {
+        assert!(loader.load_many(vec![]).await.is_empty());
+        let loads = (0..7).map(|k| loader.load(k % 4));
+        let many = (0..3).map(|k| loader.load_many(vec![k, k + 1, k]));
+        let (values, maps) = futures::future::join(
+            futures::future::join_all(loads),
+            futures::future::join_all(many),
+        )
+        .await;
+        assert_eq!(values, vec![0, 1, 2, 3, 0, 1, 2]);
+        assert!(maps.iter().all(|m| m.len() == 2));
+        assert_eq!(loader.load_many(vec![1, 2]).await.len(), 2);
+    });
+}
+
+#[test]
+fn test_duplicate_keys_share_one_request() {
+    let load_fn = LoadFnWithHistory {
+        max_batch_loaded: Arc::new(Mutex::new(0)),
+    };
+    let loader = Loader::new(load_fn.clone()).with_max_batch_size(2);
+
+    let (a, b, c) = block_on(futures::future::join3(
+        loader.load(7),
+        loader.load(7),
+        loader.load(7),
+    ));
+    assert_eq!((a, b, c), (7, 7, 7));
+    assert_eq!(*load_fn.max_batch_loaded.lock().unwrap(), 1);
+}
+
+#[test]
+fn test_load_many_shares_one_batch() {
+    let load_fn = LoadFnWithHistory {
+        max_batch_loaded: Arc::new(Mutex::new(0)),
+    };
+    let loader = Loader::new(load_fn.clone());
+
+    let ret = block_on(loader.load_many(vec![1, 2, 3, 4]));
+    assert_eq!(ret.len(), 4);
+    assert_eq!(*load_fn.max_batch_loaded.lock().unwrap(), 4);
+}
+
+// A stand-in for a fast hasher such as ahash, counting the hashers it builds.
+#[derive(Clone, Default)]
+struct CountingHasher {
+    built: Arc<Mutex<usize>>,
+}
+
+struct FnvHasher(u64);
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = (self.0 ^ *b as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+impl BuildHasher for CountingHasher {
+    type Hasher = FnvHasher;
+
+    fn build_hasher(&self) -> FnvHasher {
+        *self.built.lock().unwrap() += 1;
+        FnvHasher(0xcbf29ce484222325)
+    }
+}
+
+#[test]
+fn test_with_hasher() {
+    let hasher = CountingHasher::default();
+    let loader = Loader::with_hasher(MyLoadFn, hasher.clone());
+
+    let (a, b): (usize, usize) = block_on(futures::future::join(loader.load(1), loader.load(2)));
+    assert_eq!((a, b), (1, 2));
+    let many: HashMap<usize, usize> = block_on(loader.load_many(vec![3, 4, 3]));
+    assert_eq!(many.len(), 2);
+    assert!(*hasher.built.lock().unwrap() > 0);
+}
+
+#[test]
+fn test_on_batch_full() {
+    let full = Arc::new(Mutex::new(Vec::new()));
+    let loader = Loader::<usize, usize, _>::new(MyLoadFn)
+        .with_max_batch_size(2)
+        .with_on_batch_full({
+            let full = full.clone();
+            move |size| full.lock().unwrap().push(size)
+        });
+    let ret = block_on(loader.load_many(vec![0, 1, 2, 3, 4]));
+    assert_eq!(ret.len(), 5);
+    assert_eq!(*full.lock().unwrap(), vec![2, 2]);
+}
+
+#[test]
+fn test_max_batch_size_zero_disables_batching() {
+    let load_fn = LoadFnWithHistory {
+        max_batch_loaded: Arc::new(Mutex::new(0)),
+    };
+    let loader = Loader::new(load_fn.clone()).with_max_batch_size(0);
+
+    let (one, two, many) = block_on(futures::future::join3(
+        loader.load(1),
+        loader.load(2),
+        loader.load_many(vec![3, 4, 5]),
+    ));
+    assert_eq!((one, two), (1, 2));
+    assert_eq!(many.len(), 3);
+    assert!(many.iter().all(|(k, v)| k == v));
+    assert_eq!(*load_fn.max_batch_loaded.lock().unwrap(), 1);
+}
+
+#[derive(Clone)]
+struct LoadFnRecordingOrder {
+    batches: Arc<Mutex<Vec<Vec<usize>>>>,
+}
+
+impl BatchFn<usize, usize> for LoadFnRecordingOrder {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        self.batches.lock().unwrap().push(keys.to_vec());
+        keys.iter().map(|k| (*k, *k)).collect()
+    }
+}
+
+#[test]
+fn test_sorted_batches() {
+    let load_fn = LoadFnRecordingOrder {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone())
+        .with_max_batch_size(20)
+        .with_sorted_batches();
+
+    let keys = (0..50).rev().chain(0..10).collect::<Vec<_>>();
+    let ret = block_on(loader.load_many(keys));
+    assert_eq!(ret.len(), 50);
+    let batches = load_fn.batches.lock().unwrap();
+    assert_eq!(batches.iter().map(Vec::len).sum::<usize>(), 50);
+    for batch in batches.iter() {
+        assert!(batch.windows(2).all(|w| w[0] < w[1]), "{:?}", batch);
+    }
+}
+
+#[test]
+fn test_preserve_multiplicity() {
+    let load_fn = LoadFnRecordingOrder {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone())
+        .with_preserve_multiplicity(true)
+        .with_sorted_batches();
+    let ret = block_on(loader.load_many_ordered(vec![7, 2, 7, 7]));
+    assert_eq!(
+        ret.into_iter()
+            .map(|(k, v)| (k, v.unwrap()))
+            .collect::<Vec<_>>(),
+        vec![(7, 7), (2, 2), (7, 7), (7, 7)]
+    );
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![2, 7, 7, 7]]);
+
+    // keys are deduplicated by default
+    let loader = Loader::new(load_fn.clone()).with_sorted_batches();
+    block_on(loader.load_many(vec![7, 2, 7]));
+    assert_eq!(load_fn.batches.lock().unwrap()[1], vec![2, 7]);
+}
+
+#[test]
+fn test_load_many_chunks_large_input() {
+    let load_fn = LoadFnRecordingOrder {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone()).with_max_batch_size(100);
+    let ret = block_on(loader.try_load_many((0..1000).collect())).unwrap();
+    assert_eq!(ret.len(), 1000);
+    let batches = load_fn.batches.lock().unwrap();
+    assert!(batches.iter().all(|b| b.len() <= 100));
+    assert_eq!(batches.iter().map(Vec::len).sum::<usize>(), 1000);
+}
@@ -0,0 +1,52 @@
+use dataloader::cache::LruCache;
+use dataloader::cached::Loader;
+use dataloader::registry::LoaderRegistry;
+use dataloader::BatchFn;
+use futures::executor::block_on;
+use std::collections::HashMap;
+
+struct UserLoadFn;
+
+impl BatchFn<u64, String> for UserLoadFn {
+    async fn load(&mut self, keys: &[u64]) -> HashMap<u64, String> {
+        keys.iter().map(|k| (*k, format!("user {}", k))).collect()
+    }
+}
+
+struct TagLoadFn;
+
+impl BatchFn<String, usize> for TagLoadFn {
+    async fn load(&mut self, keys: &[String]) -> HashMap<String, usize> {
+        keys.iter().map(|k| (k.clone(), k.len())).collect()
+    }
+}
+
+#[test]
+fn assert_kinds() {
+    fn _assert_send<T: Send>() {}
+    fn _assert_sync<T: Sync>() {}
+    _assert_send::<LoaderRegistry>();
+    _assert_sync::<LoaderRegistry>();
+}
+
+#[test]
+fn test_registry() {
+    let mut registry = LoaderRegistry::new();
+    assert!(registry.register(Loader::new(UserLoadFn)).is_none());
+    assert!(registry.register(Loader::new(TagLoadFn)).is_none());
+
+    let users = registry.get::<u64, String, UserLoadFn>().unwrap();
+    let tags = registry.get::<String, usize, TagLoadFn>().unwrap();
+    assert_eq!(block_on(users.load(1)), "user 1");
+    assert_eq!(block_on(tags.load("rust".to_owned())), 4);
+
+    // handles share the registered loader's cache
+    let again = registry.get::<u64, String, UserLoadFn>().unwrap();
+    assert!(again.ptr_eq(&users));
+    assert!(block_on(again.is_cached(&1)));
+
+    assert!(registry
+        .get_with_cache::<u64, String, UserLoadFn, LruCache<u64, String>>()
+        .is_none());
+    assert!(registry.register(Loader::new(UserLoadFn)).is_some());
+}
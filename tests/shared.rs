@@ -0,0 +1,66 @@
+use dataloader::shared::Loader;
+use dataloader::BatchFn;
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// deliberately not `Clone`
+#[derive(Debug, PartialEq)]
+struct Handle {
+    id: usize,
+}
+
+#[derive(Clone)]
+struct HandleLoadFn {
+    batches: Arc<Mutex<Vec<Vec<usize>>>>,
+}
+
+impl BatchFn<usize, Handle> for HandleLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, Handle> {
+        let mut batch = keys.to_vec();
+        batch.sort();
+        self.batches.lock().unwrap().push(batch);
+        keys.iter()
+            .filter(|k| **k != 0)
+            .map(|k| (*k, Handle { id: *k }))
+            .collect()
+    }
+}
+
+#[test]
+fn assert_kinds() {
+    fn _assert_send<T: Send>() {}
+    fn _assert_sync<T: Sync>() {}
+    fn _assert_clone<T: Clone>() {}
+    _assert_send::<Loader<usize, Handle, HandleLoadFn>>();
+    _assert_sync::<Loader<usize, Handle, HandleLoadFn>>();
+    _assert_clone::<Loader<usize, Handle, HandleLoadFn>>();
+}
+
+#[test]
+fn test_load() {
+    let load_fn = HandleLoadFn {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone());
+
+    block_on(async {
+        let (a, b) = futures::join!(loader.load(1), loader.load(2));
+        assert_eq!(*a, Handle { id: 1 });
+        assert_eq!(*b, Handle { id: 2 });
+        // a cache hit hands out the same allocation
+        assert!(Arc::ptr_eq(&a, &loader.load(1).await));
+        assert!(loader.try_load(0).await.is_err());
+
+        let many = loader.load_many(vec![2, 3]).await;
+        assert!(Arc::ptr_eq(&many[&2], &b));
+        assert_eq!(*many[&3], Handle { id: 3 });
+
+        assert!(loader.prime(4, Handle { id: 40 }).await.is_none());
+        assert_eq!(*loader.load(4).await, Handle { id: 40 });
+    });
+    assert_eq!(
+        *load_fn.batches.lock().unwrap(),
+        vec![vec![1, 2], vec![0], vec![3]]
+    );
+}
@@ -0,0 +1,52 @@
+#![cfg(feature = "runtime-smol")]
+
+use dataloader::{cached, non_cached, BatchFn};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Clone)]
+struct LoadFnWithBatches {
+    batches: Arc<Mutex<Vec<Vec<usize>>>>,
+}
+
+impl BatchFn<usize, usize> for LoadFnWithBatches {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        let mut batch = keys.to_vec();
+        batch.sort();
+        self.batches.lock().unwrap().push(batch);
+        keys.iter().map(|k| (*k, *k)).collect()
+    }
+}
+
+#[test]
+fn test_cached_load() {
+    let load_fn = LoadFnWithBatches {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = cached::Loader::new(load_fn.clone());
+    smol::block_on(async {
+        let (a, b) = futures::join!(loader.load(1), loader.load(2));
+        assert_eq!((a, b), (1, 2));
+        assert_eq!(loader.load(1).await, 1);
+        assert_eq!(
+            loader
+                .try_load_timeout(3, Duration::from_millis(500))
+                .await
+                .unwrap(),
+            3
+        );
+    });
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1, 2], vec![3]]);
+}
+
+#[test]
+fn test_non_cached_load() {
+    let load_fn = LoadFnWithBatches {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = non_cached::Loader::new(load_fn.clone());
+    let ret = smol::block_on(loader.load_many(vec![1, 2, 3]));
+    assert_eq!(ret.len(), 3);
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1, 2, 3]]);
+}
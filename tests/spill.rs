@@ -0,0 +1,71 @@
+#![cfg(feature = "spill")]
+
+use dataloader::cached::{Cache, Loader};
+use dataloader::spill::SpillCache;
+use dataloader::BatchFn;
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+fn spill_dir(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("dataloader-{}-{}", name, std::process::id()))
+}
+
+#[derive(Clone)]
+struct LoadFnWithBatches {
+    batches: Arc<Mutex<Vec<Vec<u32>>>>,
+}
+
+impl BatchFn<u32, Vec<String>> for LoadFnWithBatches {
+    async fn load(&mut self, keys: &[u32]) -> HashMap<u32, Vec<String>> {
+        self.batches.lock().unwrap().push(keys.to_vec());
+        keys.iter()
+            .map(|k| (*k, vec![format!("row {}", k); 3]))
+            .collect()
+    }
+}
+
+#[test]
+fn test_spill_cache() {
+    let dir = spill_dir("cache");
+    let mut cache = SpillCache::new(2, &dir).unwrap();
+
+    for k in 0..5u32 {
+        cache.insert(k, format!("value {}", k));
+    }
+    assert_eq!(cache.in_memory_len(), 2);
+    assert_eq!(cache.spilled_len(), 3);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+
+    assert_eq!(cache.get(&0).unwrap(), "value 0");
+    assert_eq!(cache.in_memory_len(), 2);
+    assert_eq!(cache.spilled_len(), 3);
+    assert_eq!(cache.remove(&1), Some("value 1".to_string()));
+    assert_eq!(cache.get(&1), None);
+
+    cache.clear();
+    assert_eq!(cache.get(&0), None);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    std::fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn test_loader_with_spill_cache() {
+    let dir = spill_dir("loader");
+    let load_fn = LoadFnWithBatches {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let cache = SpillCache::new(3, &dir).unwrap();
+    let loader = Loader::with_cache(load_fn.clone(), cache);
+
+    let first = block_on(loader.load_many((0..10).collect()));
+    let again = block_on(loader.load_many((0..10).collect()));
+    assert_eq!(first, again);
+    assert_eq!(first[&7], vec!["row 7".to_string(); 3]);
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 1);
+
+    drop(loader);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    std::fs::remove_dir(&dir).unwrap();
+}
@@ -0,0 +1,44 @@
+#![cfg(feature = "testing")]
+
+use dataloader::cached::Loader;
+use dataloader::BatchFn;
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::future::ready;
+
+struct MyLoadFn;
+
+impl BatchFn<usize, usize> for MyLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        let ret = keys.iter().map(|v| (*v, *v)).collect::<HashMap<_, _>>();
+        ready(ret).await
+    }
+}
+
+#[test]
+fn test_next_batch() {
+    let loader = Loader::new(MyLoadFn).with_max_batch_size(10);
+
+    let (v1, v2, batch) = block_on(futures::future::join3(
+        loader.load(1),
+        loader.load(2),
+        loader.next_batch(),
+    ));
+    assert_eq!((v1, v2), (1, 2));
+
+    let mut keys = batch.keys;
+    keys.sort();
+    assert_eq!(keys, vec![1, 2]);
+}
+
+#[test]
+fn test_next_batch_skips_cache_hits() {
+    let loader = Loader::new(MyLoadFn).with_max_batch_size(10);
+    block_on(loader.prime(1, 1));
+
+    let (_, batch) = block_on(futures::future::join(
+        loader.load_many(vec![1, 3]),
+        loader.next_batch(),
+    ));
+    assert_eq!(batch.keys, vec![3]);
+}
@@ -0,0 +1,766 @@
+use dataloader::cached::Loader;
+use dataloader::wait::sleep_fn;
+use dataloader::{BatchFn, LoadError, SharedBatchFn};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+fn run<F: Future>(f: F) -> F::Output {
+    #[cfg(feature = "runtime-tokio")]
+    return tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap()
+        .block_on(f);
+    #[cfg(not(feature = "runtime-tokio"))]
+    futures::executor::block_on(f)
+}
+
+async fn sleep(duration: Duration) {
+    #[cfg(feature = "runtime-tokio")]
+    tokio::time::sleep(duration).await;
+    #[cfg(feature = "runtime-smol")]
+    smol::Timer::after(duration).await;
+    #[cfg(feature = "runtime-async-std")]
+    async_std::task::sleep(duration).await;
+}
+
+#[derive(Clone)]
+struct SlowLoadFn {
+    delay: Duration,
+    batches: Arc<Mutex<Vec<Vec<usize>>>>,
+    in_flight: Arc<AtomicUsize>,
+    max_in_flight: Arc<AtomicUsize>,
+}
+
+impl SlowLoadFn {
+    fn new(delay: Duration) -> Self {
+        SlowLoadFn {
+            delay,
+            batches: Arc::new(Mutex::new(Vec::new())),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            max_in_flight: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+impl BatchFn<usize, usize> for SlowLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        self.batches.lock().unwrap().push(keys.to_vec());
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+        sleep(self.delay).await;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        keys.iter().map(|k| (*k, *k)).collect()
+    }
+}
+
+#[test]
+fn test_load_or_falls_back_on_timeout() {
+    let load_fn = SlowLoadFn::new(Duration::from_millis(50));
+    let loader = Loader::new(load_fn.clone());
+
+    // the first call dispatches the batch and finishes it after load_or gives up on it
+    let (loaded, fallback) = run(futures::future::join(
+        loader.try_load(1),
+        loader.load_or(1, Duration::from_millis(5), 0),
+    ));
+    assert_eq!((loaded.unwrap(), fallback), (1, 0));
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1]]);
+    assert_eq!(run(loader.load_or(1, Duration::ZERO, 0)), 1);
+}
+
+#[test]
+fn test_load_or_timeout_leaves_key_queued() {
+    let load_fn = SlowLoadFn::new(Duration::ZERO);
+    let loader =
+        Loader::new(load_fn.clone()).with_custom_wait_for_work(sleep_fn(Duration::from_millis(50)));
+
+    run(async {
+        assert_eq!(loader.load_or(1, Duration::from_millis(5), 0).await, 0);
+        assert!(!loader.is_cached(&1).await);
+        // the next dispatch loads the key load_or gave up on
+        assert_eq!(loader.load(2).await, 2);
+        assert!(loader.is_cached(&1).await);
+    });
+    let mut batches = load_fn.batches.lock().unwrap().clone();
+    batches[0].sort();
+    assert_eq!(batches, vec![vec![1, 2]]);
+}
+
+#[test]
+fn test_load_or_dispatching_ignores_timeout() {
+    let loader = Loader::new(SlowLoadFn::new(Duration::from_millis(20)));
+
+    assert_eq!(run(loader.load_or(1, Duration::from_millis(5), 0)), 1);
+}
+
+#[test]
+fn test_try_load_timeout() {
+    let load_fn = SlowLoadFn::new(Duration::from_millis(50));
+    let loader = Loader::new(load_fn.clone());
+
+    let started = Instant::now();
+    let (timed_out, loaded) = run(futures::future::join(
+        loader.try_load_timeout(1, Duration::from_millis(5)),
+        loader.try_load(1),
+    ));
+    assert_eq!(timed_out, Err(LoadError::Timeout(1)));
+    assert_eq!(loaded, Ok(1));
+    assert!(started.elapsed() < Duration::from_millis(200));
+    assert_eq!(run(loader.try_load_timeout(1, Duration::ZERO)), Ok(1));
+    assert_eq!(run(loader.peek(&1)), Some(1));
+}
+
+#[test]
+fn test_load_or_within_timeout() {
+    let loader = Loader::new(SlowLoadFn::new(Duration::from_millis(1)));
+
+    let v = run(loader.load_or(1, Duration::from_secs(5), 0));
+    assert_eq!(v, 1);
+}
+
+#[test]
+fn test_batches_take_turns_by_default() {
+    let load_fn = SlowLoadFn::new(Duration::from_millis(10));
+    let loader = Loader::new(load_fn.clone()).with_max_batch_size(1);
+
+    let (a, b) = run(futures::future::join(loader.load(1), loader.load(2)));
+    assert_eq!((a, b), (1, 2));
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 2);
+    assert_eq!(load_fn.max_in_flight.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_concurrent_dispatch() {
+    let load_fn = SlowLoadFn::new(Duration::from_millis(10));
+    let loader = Loader::new(load_fn.clone())
+        .with_max_batch_size(1)
+        .with_concurrent_dispatch();
+
+    let (a, b) = run(futures::future::join(loader.load(1), loader.load(2)));
+    assert_eq!((a, b), (1, 2));
+    assert_eq!(load_fn.max_in_flight.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_max_concurrent_batches() {
+    let load_fn = SlowLoadFn::new(Duration::from_millis(10));
+    let loader = Loader::new(load_fn.clone())
+        .with_max_batch_size(1)
+        .with_concurrent_dispatch()
+        .with_max_concurrent_batches(1);
+
+    let values = run(futures::future::join_all((0..4).map(|k| loader.load(k))));
+    assert_eq!(values, vec![0, 1, 2, 3]);
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 4);
+    assert_eq!(load_fn.max_in_flight.load(Ordering::SeqCst), 1);
+}
+
+struct SharedSlowLoadFn {
+    delay: Duration,
+    in_flight: AtomicUsize,
+    max_in_flight: AtomicUsize,
+}
+
+impl SharedBatchFn<usize, usize> for SharedSlowLoadFn {
+    async fn load(&self, keys: &[usize]) -> HashMap<usize, usize> {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+        sleep(self.delay).await;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        keys.iter().map(|k| (*k, *k)).collect()
+    }
+}
+
+#[test]
+fn test_concurrent_dispatch_shared_batch_fn() {
+    let load_fn = Arc::new(SharedSlowLoadFn {
+        delay: Duration::from_millis(10),
+        in_flight: AtomicUsize::new(0),
+        max_in_flight: AtomicUsize::new(0),
+    });
+    let loader = Loader::new(load_fn.clone())
+        .with_max_batch_size(2)
+        .with_concurrent_dispatch();
+
+    let ret = run(futures::future::join3(
+        loader.load_many(vec![1, 2]),
+        loader.load_many(vec![3, 4]),
+        loader.load(5),
+    ));
+    assert_eq!(ret.0, vec![(1, 1), (2, 2)].into_iter().collect());
+    assert_eq!(ret.1, vec![(3, 3), (4, 4)].into_iter().collect());
+    assert_eq!(ret.2, 5);
+    assert!(load_fn.max_in_flight.load(Ordering::SeqCst) >= 2);
+}
+
+#[test]
+fn test_cache_hits_during_slow_batch() {
+    let load_fn = SlowLoadFn::new(Duration::from_millis(50));
+    let loader = Loader::new(load_fn.clone());
+    let order = Mutex::new(Vec::new());
+
+    run(async {
+        loader.prime(2, 20).await;
+        futures::future::join(
+            async {
+                assert_eq!(loader.load(1).await, 1);
+                order.lock().unwrap().push(1);
+            },
+            async {
+                sleep(Duration::from_millis(5)).await;
+                assert_eq!(loader.load(2).await, 20);
+                order.lock().unwrap().push(2);
+            },
+        )
+        .await;
+    });
+    assert_eq!(*order.lock().unwrap(), vec![2, 1]);
+}
+
+#[test]
+fn test_waiters_share_in_flight_batch() {
+    let load_fn = SlowLoadFn::new(Duration::from_millis(20));
+    let loader = Loader::new(load_fn.clone());
+
+    let (a, b) = run(futures::future::join(loader.load(1), async {
+        sleep(Duration::from_millis(5)).await;
+        loader.load(1).await
+    }));
+    assert_eq!((a, b), (1, 1));
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1]]);
+}
+
+#[test]
+fn test_value_ttl() {
+    let load_fn = SlowLoadFn::new(Duration::from_millis(1));
+    // even keys expire after 20ms, odd ones after an hour
+    let loader = Loader::new(load_fn.clone()).with_value_ttl(|v: &usize| {
+        Some(Duration::from_millis(if v.is_multiple_of(2) {
+            20
+        } else {
+            3_600_000
+        }))
+    });
+
+    run(async {
+        assert_eq!(loader.load_many(vec![1, 2]).await.len(), 2);
+        sleep(Duration::from_millis(40)).await;
+        assert_eq!(loader.load(1).await, 1);
+        assert_eq!(loader.load(2).await, 2);
+    });
+    let mut batches = load_fn.batches.lock().unwrap().clone();
+    batches[0].sort();
+    assert_eq!(batches, vec![vec![1, 2], vec![2]]);
+}
+
+#[test]
+fn test_value_ttl_none_never_expires() {
+    let load_fn = SlowLoadFn::new(Duration::from_millis(1));
+    let loader = Loader::new(load_fn.clone()).with_value_ttl(|_| None);
+
+    run(async {
+        loader.load(1).await;
+        sleep(Duration::from_millis(10)).await;
+        loader.load(1).await;
+    });
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn test_value_ttl_expiry_calls_on_evict() {
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    let loader = Loader::new(SlowLoadFn::new(Duration::from_millis(1)))
+        .with_value_ttl(|_| Some(Duration::from_millis(10)))
+        .with_on_evict({
+            let evicted = evicted.clone();
+            move |k: &usize, _: &usize| evicted.lock().unwrap().push(*k)
+        });
+
+    run(async {
+        loader.load(1).await;
+        sleep(Duration::from_millis(20)).await;
+        loader.load(1).await;
+    });
+    assert_eq!(*evicted.lock().unwrap(), vec![1]);
+}
+
+#[test]
+fn test_rate_limit() {
+    let load_fn = SlowLoadFn::new(Duration::from_millis(1));
+    let loader = Loader::new(load_fn.clone()).with_rate_limit(20);
+
+    let started = Instant::now();
+    run(async {
+        loader.load(1).await;
+        let (a, b) = futures::future::join(loader.load(2), loader.load(3)).await;
+        assert_eq!((a, b), (2, 3));
+        loader.load(4).await;
+    });
+    assert!(started.elapsed() >= Duration::from_millis(100));
+    let mut batches = load_fn.batches.lock().unwrap().clone();
+    batches[1].sort();
+    assert_eq!(batches, vec![vec![1], vec![2, 3], vec![4]]);
+}
+
+#[test]
+fn test_rate_limit_accumulates_keys() {
+    let load_fn = SlowLoadFn::new(Duration::from_millis(1));
+    let loader = Loader::new(load_fn.clone())
+        .with_rate_limit(20)
+        .with_max_batch_size(100);
+
+    let loader = &loader;
+    run(async {
+        loader.load(0).await;
+        futures::future::join_all((1..=5).map(|k| async move {
+            sleep(Duration::from_millis(k * 5)).await;
+            loader.load(k as usize).await
+        }))
+        .await;
+    });
+    let batches = load_fn.batches.lock().unwrap();
+    assert_eq!(batches.len(), 2);
+    assert_eq!(batches[1].len(), 5);
+}
+
+#[test]
+fn test_recent_stats_latency_and_window() {
+    let loader = Loader::new(SlowLoadFn::new(Duration::from_millis(20))).with_recent_stats();
+
+    run(async {
+        loader.load(1).await;
+        sleep(Duration::from_millis(50)).await;
+        loader.load(2).await;
+    });
+    let stats = loader.recent_stats(Duration::from_secs(60));
+    assert_eq!(stats.batches, 2);
+    assert!(stats.p99_batch_latency >= Duration::from_millis(20));
+    assert_eq!(loader.recent_stats(Duration::from_millis(40)).batches, 1);
+}
+
+#[test]
+fn test_load_many_wait_for_work() {
+    let load_fn = SlowLoadFn::new(Duration::from_millis(1));
+    let loader = Loader::new(load_fn.clone())
+        .with_custom_wait_for_work(dataloader::wait::sleep_fn(Duration::from_millis(200)))
+        .with_load_many_wait_for_work(dataloader::wait::yield_fn(0));
+
+    let started = Instant::now();
+    let ret = run(loader.load_many(vec![1, 2, 3]));
+    assert_eq!(ret.len(), 3);
+    assert!(started.elapsed() < Duration::from_millis(200));
+
+    let started = Instant::now();
+    assert_eq!(run(loader.load(4)), 4);
+    assert!(started.elapsed() >= Duration::from_millis(200));
+}
+
+#[test]
+fn test_split() {
+    let load_fn = SlowLoadFn::new(Duration::from_millis(1));
+    let loader = Loader::new(load_fn.clone()).with_concurrent_dispatch();
+
+    let parts = run(loader.split(2));
+    assert_eq!(parts.len(), 2);
+    assert!(!parts[0].ptr_eq(&parts[1]));
+    run(async {
+        let (a, b) = futures::future::join(
+            parts[0].load_many(vec![1, 2]),
+            parts[1].load_many(vec![3, 4]),
+        )
+        .await;
+        assert_eq!(a.len() + b.len(), 4);
+        parts[0].load(2).await;
+    });
+    let mut batches = load_fn.batches.lock().unwrap().clone();
+    batches.iter_mut().for_each(|b| b.sort());
+    batches.sort();
+    assert_eq!(batches, vec![vec![1, 2], vec![3, 4]]);
+    assert_eq!(load_fn.max_in_flight.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_max_batch_size_caps_distinct_keys() {
+    let load_fn = SlowLoadFn::new(Duration::from_millis(1));
+    let loader = Loader::new(load_fn.clone())
+        .with_rate_limit(50)
+        .with_max_batch_size(3);
+
+    let loader = &loader;
+    let ret = run(futures::future::join_all((0..20).map(|k| async move {
+        sleep(Duration::from_millis(k as u64)).await;
+        loader.load(k % 10).await
+    })));
+    assert_eq!(ret, (0..20).map(|k| k % 10).collect::<Vec<_>>());
+    let batches = load_fn.batches.lock().unwrap().clone();
+    assert!(batches.iter().all(|b| b.len() <= 3), "{:?}", batches);
+    assert_eq!(batches.iter().map(|b| b.len()).sum::<usize>(), 10);
+}
+
+#[test]
+fn test_prime_if_absent_skips_keys_in_flight() {
+    let loader = Loader::new(SlowLoadFn::new(Duration::from_millis(20)));
+
+    run(async {
+        let (v, primed) = futures::future::join(loader.load(1), async {
+            sleep(Duration::from_millis(5)).await;
+            loader.prime_if_absent(1, 99).await
+        })
+        .await;
+        assert_eq!(v, 1);
+        assert!(!primed);
+        assert_eq!(loader.load(1).await, 1);
+    });
+}
+
+#[test]
+fn test_batch_window() {
+    let load_fn = SlowLoadFn::new(Duration::from_millis(1));
+    let loader = Loader::new(load_fn.clone()).with_batch_window(Duration::from_millis(50));
+
+    let loader = &loader;
+    let ret = run(futures::future::join_all((0..4).map(|k| async move {
+        sleep(Duration::from_millis(k as u64 * 5)).await;
+        loader.load(k).await
+    })));
+    assert_eq!(ret, vec![0, 1, 2, 3]);
+    let mut batches = load_fn.batches.lock().unwrap().clone();
+    batches[0].sort();
+    assert_eq!(batches, vec![vec![0, 1, 2, 3]]);
+}
+
+#[test]
+fn test_flush() {
+    let load_fn = SlowLoadFn::new(Duration::from_millis(1));
+    let loader = Loader::new(load_fn.clone()).with_batch_window(Duration::from_secs(5));
+
+    let started = Instant::now();
+    let loader = &loader;
+    let (ret, _) = run(futures::future::join(
+        futures::future::join_all((0..3).map(|k| loader.try_load(k))),
+        async {
+            sleep(Duration::from_millis(10)).await;
+            loader.flush().await;
+        },
+    ));
+    assert_eq!(
+        ret.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+        vec![0, 1, 2]
+    );
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn test_batch_trigger() {
+    let load_fn = SlowLoadFn::new(Duration::from_millis(1));
+    let loader = Loader::new(load_fn.clone()).with_batch_trigger(5, Duration::from_secs(5));
+
+    let started = Instant::now();
+    let ret = run(futures::future::join_all((0..5).map(|k| loader.load(k))));
+    assert_eq!(ret, vec![0, 1, 2, 3, 4]);
+    assert!(started.elapsed() < Duration::from_secs(1));
+    let mut batches = load_fn.batches.lock().unwrap().clone();
+    batches.iter_mut().for_each(|b| b.sort());
+    assert_eq!(batches, vec![vec![0, 1, 2, 3, 4]]);
+
+    // too few keys wait out the delay
+    let loader = Loader::new(load_fn.clone()).with_batch_trigger(5, Duration::from_millis(20));
+    let started = Instant::now();
+    let ret = run(futures::future::join_all((5..7).map(|k| loader.load(k))));
+    assert_eq!(ret, vec![5, 6]);
+    assert!(started.elapsed() >= Duration::from_millis(20));
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn test_clear_pending() {
+    let load_fn = SlowLoadFn::new(Duration::from_millis(1));
+    let loader = Loader::new(load_fn.clone()).with_batch_window(Duration::from_millis(50));
+
+    let started = Instant::now();
+    let loader = &loader;
+    let (ret, _) = run(futures::future::join(loader.try_load(1), async {
+        sleep(Duration::from_millis(10)).await;
+        loader.clear_pending().await;
+    }));
+    assert_eq!(ret, Err(LoadError::KeyNotFound(1)));
+    assert!(started.elapsed() < Duration::from_millis(50));
+    assert_eq!(run(loader.pending_count()), 0);
+    run(sleep(Duration::from_millis(60)));
+    assert!(load_fn.batches.lock().unwrap().is_empty());
+
+    assert_eq!(run(loader.try_load(1)), Ok(1));
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1]]);
+}
+
+#[test]
+fn test_max_pending() {
+    let load_fn = SlowLoadFn::new(Duration::from_millis(1));
+    let loader = Loader::new(load_fn.clone())
+        .with_rate_limit(50)
+        .with_max_batch_size(100)
+        .with_max_pending(5);
+
+    let loader = &loader;
+    let done = AtomicUsize::new(0);
+    let max_seen = AtomicUsize::new(0);
+    let (many, ones, _) = run(futures::future::join3(
+        async {
+            let ret = loader.load_many((0..12).collect()).await;
+            done.fetch_add(1, Ordering::SeqCst);
+            ret
+        },
+        async {
+            let ret = futures::future::join_all((12..40).map(|k| async move {
+                sleep(Duration::from_millis(k as u64)).await;
+                loader.load(k).await
+            }))
+            .await;
+            done.fetch_add(1, Ordering::SeqCst);
+            ret
+        },
+        async {
+            while done.load(Ordering::SeqCst) < 2 {
+                max_seen.fetch_max(loader.pending_count().await, Ordering::SeqCst);
+                sleep(Duration::from_millis(1)).await;
+            }
+        },
+    ));
+    assert_eq!(many, (0..12).map(|k| (k, k)).collect());
+    assert_eq!(ones, (12..40).collect::<Vec<_>>());
+    assert!(max_seen.load(Ordering::SeqCst) <= 5);
+    let batches = load_fn.batches.lock().unwrap().clone();
+    assert!(batches.iter().all(|b| b.len() <= 5), "{:?}", batches);
+    assert_eq!(batches.iter().map(|b| b.len()).sum::<usize>(), 40);
+}
+
+#[test]
+fn test_pending_keys() {
+    let load_fn = SlowLoadFn::new(Duration::from_millis(1));
+    let loader = Loader::new(load_fn.clone()).with_batch_window(Duration::from_secs(5));
+
+    let loader = &loader;
+    let (ret, _) = run(futures::future::join(
+        futures::future::join_all((0..3).map(|k| loader.try_load(k))),
+        async {
+            sleep(Duration::from_millis(10)).await;
+            let mut pending = loader.pending_keys().await;
+            pending.sort_unstable();
+            assert_eq!(pending, vec![0, 1, 2]);
+            assert_eq!(loader.pending_count().await, 3);
+            assert!(load_fn.batches.lock().unwrap().is_empty());
+            loader.flush().await;
+            assert!(loader.pending_keys().await.is_empty());
+            assert_eq!(loader.pending_count().await, 0);
+        },
+    ));
+    assert_eq!(
+        ret.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+        vec![0, 1, 2]
+    );
+}
+
+#[test]
+fn test_try_load_with_cancel() {
+    let load_fn = SlowLoadFn::new(Duration::from_millis(1));
+    let loader = Loader::new(load_fn.clone()).with_batch_window(Duration::from_millis(50));
+
+    // the only caller for key 1 cancels, so no batch is dispatched for it
+    let started = Instant::now();
+    let ret = run(loader.try_load_with_cancel(1, sleep(Duration::from_millis(10))));
+    assert_eq!(ret.unwrap(), None);
+    assert!(started.elapsed() < Duration::from_millis(40));
+    assert_eq!(run(loader.load(2)), 2);
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![2]]);
+
+    // another caller still wants key 3, so it stays queued for them
+    let (cancelled, loaded) = run(futures::future::join(
+        loader.try_load_with_cancel(3, sleep(Duration::from_millis(10))),
+        loader.try_load_with_cancel(3, futures::future::pending()),
+    ));
+    assert_eq!(cancelled.unwrap(), None);
+    assert_eq!(loaded.unwrap(), Some(3));
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 2);
+
+    // a plain load of the same key keeps it queued too
+    let (cancelled, loaded) = run(futures::future::join(
+        loader.try_load_with_cancel(4, sleep(Duration::from_millis(10))),
+        loader.load(4),
+    ));
+    assert_eq!(cancelled.unwrap(), None);
+    assert_eq!(loaded, 4);
+    assert_eq!(
+        run(loader.try_load_with_cancel(4, sleep(Duration::ZERO))).unwrap(),
+        Some(4)
+    );
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum FetchError {
+    Transient,
+    Fatal,
+}
+
+#[derive(Clone)]
+struct FlakyLoadFn {
+    failures: Arc<AtomicUsize>,
+    calls: Arc<AtomicUsize>,
+}
+
+impl BatchFn<usize, Result<usize, FetchError>> for FlakyLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, Result<usize, FetchError>> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        let failing = self
+            .failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+        keys.iter()
+            .map(|k| match *k {
+                0 => (*k, Err(FetchError::Fatal)),
+                _ if failing => (*k, Err(FetchError::Transient)),
+                _ => (*k, Ok(*k)),
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_retry() {
+    let load_fn = FlakyLoadFn {
+        failures: Arc::new(AtomicUsize::new(2)),
+        calls: Arc::new(AtomicUsize::new(0)),
+    };
+    let loader = Loader::new(load_fn.clone())
+        .with_retry(3, Duration::from_millis(5), |e| *e == FetchError::Transient);
+
+    let started = Instant::now();
+    let (a, b, c) = run(futures::future::join3(
+        loader.load(0),
+        loader.load(1),
+        loader.load(2),
+    ));
+    assert_eq!((a, b, c), (Err(FetchError::Fatal), Ok(1), Ok(2)));
+    assert_eq!(load_fn.calls.load(Ordering::SeqCst), 3);
+    assert!(started.elapsed() >= Duration::from_millis(10));
+
+    let load_fn = FlakyLoadFn {
+        failures: Arc::new(AtomicUsize::new(5)),
+        calls: Arc::new(AtomicUsize::new(0)),
+    };
+    let loader = Loader::new(load_fn.clone())
+        .with_retry(1, Duration::from_millis(1), |e| *e == FetchError::Transient);
+    assert_eq!(run(loader.load(1)), Err(FetchError::Transient));
+    assert_eq!(load_fn.calls.load(Ordering::SeqCst), 2);
+}
+
+// Fails key 1 on its first load only, after a delay.
+#[derive(Clone)]
+struct FailsOnceLoadFn {
+    failed: Arc<AtomicUsize>,
+}
+
+impl BatchFn<usize, Result<usize, FetchError>> for FailsOnceLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, Result<usize, FetchError>> {
+        sleep(Duration::from_millis(5)).await;
+        keys.iter()
+            .map(|k| match *k {
+                1 if self.failed.fetch_add(1, Ordering::SeqCst) == 0 => {
+                    (*k, Err(FetchError::Transient))
+                }
+                _ => (*k, Ok(*k)),
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_retry_backoff_releases_batch_permit() {
+    let loader = Loader::new(FailsOnceLoadFn {
+        failed: Arc::new(AtomicUsize::new(0)),
+    })
+    .with_max_batch_size(1)
+    .with_concurrent_dispatch()
+    .with_max_concurrent_batches(1)
+    .with_retry(1, Duration::from_millis(200), |e| {
+        *e == FetchError::Transient
+    });
+
+    let started = Instant::now();
+    let (a, (b, b_elapsed)) = run(futures::future::join(loader.load(1), async {
+        let b = loader.load(2).await;
+        (b, started.elapsed())
+    }));
+    assert_eq!((a, b), (Ok(1), Ok(2)));
+    // key 2 runs while key 1 backs off
+    assert!(b_elapsed < Duration::from_millis(200));
+}
+
+#[test]
+fn test_cancelled_wait_for_batch_permit() {
+    let load_fn = SlowLoadFn::new(Duration::from_millis(20));
+    let loader = Loader::new(load_fn.clone())
+        .with_max_batch_size(1)
+        .with_concurrent_dispatch()
+        .with_max_concurrent_batches(1);
+
+    let (a, cancelled, c) = run(futures::future::join3(
+        loader.load(1),
+        loader.try_load_timeout(2, Duration::from_millis(5)),
+        loader.load(3),
+    ));
+    assert_eq!((a, cancelled, c), (1, Err(LoadError::Timeout(2)), 3));
+    assert_eq!(run(loader.load(2)), 2);
+    assert_eq!(load_fn.max_in_flight.load(Ordering::SeqCst), 1);
+}
+
+struct LoadFnPanickingOn13;
+
+impl BatchFn<usize, usize> for LoadFnPanickingOn13 {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        if keys.contains(&13) {
+            panic!("unlucky batch");
+        }
+        keys.iter().map(|k| (*k, *k)).collect()
+    }
+}
+
+#[test]
+fn test_batch_panicked_spares_keys_left_pending() {
+    // which of the two keys goes first depends on hashing, so try a few loaders
+    for _ in 0..8 {
+        let loader = Loader::new(LoadFnPanickingOn13)
+            .with_max_batch_size(1)
+            .with_rate_limit(50);
+        let (a, b) = run(async {
+            // use up the first token, so both keys queue while the next batch waits for one
+            loader.load(0).await;
+            futures::future::join(loader.try_load(13), loader.try_load(1)).await
+        });
+        assert_eq!(a, Err(LoadError::BatchPanicked(13)));
+        assert_eq!(b, Ok(1));
+    }
+}
+
+#[test]
+fn test_load_immediate_hits_skip_rate_limit() {
+    let load_fn = SlowLoadFn::new(Duration::from_millis(1));
+    let loader = Loader::new(load_fn.clone()).with_rate_limit(10);
+
+    run(async {
+        assert_eq!(loader.load_immediate(1).await, Ok(1));
+        let started = Instant::now();
+        for _ in 0..5 {
+            assert_eq!(loader.load_immediate(1).await, Ok(1));
+        }
+        assert!(started.elapsed() < Duration::from_millis(50));
+        // a miss still waits for its turn
+        assert_eq!(loader.load_immediate(2).await, Ok(2));
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    });
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1], vec![2]]);
+}
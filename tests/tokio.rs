@@ -0,0 +1,65 @@
+#![cfg(feature = "runtime-tokio")]
+
+use dataloader::{cached, non_cached, BatchFn};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone)]
+struct LoadFnWithBatches {
+    batches: Arc<Mutex<Vec<Vec<usize>>>>,
+}
+
+impl BatchFn<usize, usize> for LoadFnWithBatches {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        let mut batch = keys.to_vec();
+        batch.sort();
+        self.batches.lock().unwrap().push(batch);
+        keys.iter().map(|k| (*k, *k)).collect()
+    }
+}
+
+fn load_fn() -> LoadFnWithBatches {
+    LoadFnWithBatches {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    }
+}
+
+#[tokio::test]
+async fn test_cached_load() {
+    let load_fn = load_fn();
+    let loader = cached::Loader::new(load_fn.clone());
+    let (a, b) = tokio::join!(loader.load(1), loader.load(2));
+    assert_eq!((a, b), (1, 2));
+    assert_eq!(loader.load(1).await, 1);
+    assert_eq!(*load_fn.batches.lock().unwrap(), vec![vec![1, 2]]);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_cached_load_from_spawned_tasks() {
+    let load_fn = load_fn();
+    let loader = cached::Loader::new(load_fn.clone());
+    let tasks = (0..10)
+        .map(|k| {
+            let loader = loader.clone();
+            tokio::spawn(async move { loader.load(k % 5).await })
+        })
+        .collect::<Vec<_>>();
+    for (k, task) in tasks.into_iter().enumerate() {
+        assert_eq!(task.await.unwrap(), k % 5);
+    }
+    let loaded = load_fn.batches.lock().unwrap().concat();
+    assert_eq!(loaded.len(), 5);
+}
+
+#[tokio::test]
+async fn test_non_cached_load() {
+    let load_fn = load_fn();
+    let loader = non_cached::Loader::new(load_fn.clone());
+    let (a, b) = tokio::join!(loader.load(1), loader.load(2));
+    assert_eq!((a, b), (1, 2));
+    assert_eq!(loader.load_many(vec![3, 4]).await.len(), 2);
+    assert_eq!(
+        *load_fn.batches.lock().unwrap(),
+        vec![vec![1, 2], vec![3, 4]]
+    );
+}
@@ -0,0 +1,92 @@
+#![cfg(feature = "tracing")]
+
+use dataloader::{cached, non_cached, BatchFn};
+use futures::executor::block_on;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+struct MyLoadFn;
+
+impl BatchFn<usize, usize> for MyLoadFn {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        keys.iter().filter(|k| **k != 3).map(|k| (*k, *k)).collect()
+    }
+}
+
+type FieldValues = Vec<(String, String)>;
+
+#[derive(Default)]
+struct Fields(FieldValues);
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0
+            .push((field.name().to_string(), format!("{:?}", value)));
+    }
+}
+
+// Records the fields of every span and event it sees.
+#[derive(Clone, Default)]
+struct Recorder {
+    spans: Arc<Mutex<Vec<(String, FieldValues)>>>,
+    events: Arc<Mutex<Vec<FieldValues>>>,
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut fields = Fields::default();
+        span.record(&mut fields);
+        let mut spans = self.spans.lock().unwrap();
+        spans.push((span.metadata().name().to_string(), fields.0));
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        self.events.lock().unwrap().push(fields.0);
+    }
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+fn field<'a>(fields: &'a [(String, String)], name: &str) -> &'a str {
+    &fields.iter().find(|(n, _)| n == name).unwrap().1
+}
+
+#[test]
+fn test_batch_spans() {
+    let recorder = Recorder::default();
+    tracing::subscriber::with_default(recorder.clone(), || {
+        let loader = cached::Loader::new(MyLoadFn);
+        block_on(loader.load_many(vec![1, 2]));
+        let loader = non_cached::Loader::new(MyLoadFn);
+        let _ = block_on(loader.try_load_many(vec![1, 2, 3]));
+    });
+
+    let spans = recorder.spans.lock().unwrap();
+    assert_eq!(spans.len(), 2);
+    assert!(spans.iter().all(|(name, _)| name == "dataloader.batch"));
+    assert_eq!(field(&spans[0].1, "keys"), "2");
+    assert_eq!(field(&spans[1].1, "keys"), "3");
+
+    let events = recorder.events.lock().unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(field(&events[0], "resolved"), "2");
+    assert_eq!(field(&events[1], "resolved"), "2");
+    assert!(field(&events[1], "elapsed_us").parse::<u64>().is_ok());
+}
@@ -0,0 +1,96 @@
+use dataloader::cached::Loader;
+use dataloader::wait::{notified, race, sleep_fn, then, yield_fn, Notify};
+use dataloader::BatchFn;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+fn run<F: Future>(f: F) -> F::Output {
+    #[cfg(feature = "runtime-tokio")]
+    return tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap()
+        .block_on(f);
+    #[cfg(not(feature = "runtime-tokio"))]
+    futures::executor::block_on(f)
+}
+
+#[derive(Clone)]
+struct LoadFnWithBatches {
+    batches: Arc<Mutex<Vec<Vec<usize>>>>,
+}
+
+impl BatchFn<usize, usize> for LoadFnWithBatches {
+    async fn load(&mut self, keys: &[usize]) -> HashMap<usize, usize> {
+        self.batches.lock().unwrap().push(keys.to_vec());
+        keys.iter().map(|k| (*k, *k)).collect()
+    }
+}
+
+#[test]
+fn test_race_resolves_with_first() {
+    let wait = race(yield_fn(1), sleep_fn(Duration::from_secs(3600)));
+    let started = Instant::now();
+    run(async { wait().await });
+    assert!(started.elapsed() < Duration::from_secs(60));
+
+    let wait = race(sleep_fn(Duration::from_secs(3600)), yield_fn(1));
+    run(async { wait().await });
+    assert!(started.elapsed() < Duration::from_secs(60));
+}
+
+#[test]
+fn test_then_waits_for_both() {
+    let wait = then(yield_fn(1), sleep_fn(Duration::from_millis(20)));
+    let started = Instant::now();
+    run(async { wait().await });
+    assert!(started.elapsed() >= Duration::from_millis(20));
+}
+
+#[test]
+fn test_composite_wait_for_work() {
+    let load_fn = LoadFnWithBatches {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let loader = Loader::new(load_fn.clone())
+        .with_custom_wait_for_work(race(yield_fn(10), sleep_fn(Duration::from_millis(1))));
+
+    let (a, b) = run(futures::future::join(loader.load(1), loader.load(2)));
+    assert_eq!((a, b), (1, 2));
+    let mut batches = load_fn.batches.lock().unwrap().clone();
+    batches[0].sort();
+    assert_eq!(batches, vec![vec![1, 2]]);
+}
+
+#[test]
+fn test_dispatch_notifier() {
+    let load_fn = LoadFnWithBatches {
+        batches: Arc::new(Mutex::new(Vec::new())),
+    };
+    let notify = Arc::new(Notify::new());
+    let loader = Loader::new(load_fn.clone()).with_dispatch_notifier(notify.clone());
+
+    let (a, b, _) = run(async {
+        futures::join!(loader.load(1), loader.load(2), async {
+            sleep_fn(Duration::from_millis(20))().await;
+            assert!(load_fn.batches.lock().unwrap().is_empty());
+            notify.notify();
+        })
+    });
+    assert_eq!((a, b), (1, 2));
+    assert_eq!(load_fn.batches.lock().unwrap().len(), 1);
+
+    // a notification only ends waits that already started
+    notify.notify();
+    let wait = notified(notify.clone());
+    let (_, notified_first) = run(async {
+        futures::join!(wait(), async {
+            sleep_fn(Duration::from_millis(5))().await;
+            notify.notify();
+            true
+        })
+    });
+    assert!(notified_first);
+}